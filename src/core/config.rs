@@ -1,22 +1,463 @@
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use toml;
+use serde::{Deserialize, Serialize};
 
-pub fn from_file<T>(file: &str) -> Result<T, toml::de::Error>
+/// Serialisation format of a configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// JSON when the text starts with `{`, TOML otherwise.
+    pub fn sniff(contents: &str) -> Self {
+        if contents.trim_start().starts_with('{') {
+            ConfigFormat::Json
+        } else {
+            ConfigFormat::Toml
+        }
+    }
+
+    /// The format of `path` by extension, `None` for other extensions.
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Some(ConfigFormat::Json),
+            Some("toml") => Some(ConfigFormat::Toml),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigFormat::Toml => write!(f, "TOML"),
+            ConfigFormat::Json => write!(f, "JSON"),
+        }
+    }
+}
+
+/// Reads a TOML or JSON configuration.
+///
+/// `file` may also be a model directory, in which case its `config.json` (as shipped by
+/// Hugging Face) or else `config.toml` is read. The format follows the extension; files
+/// with another extension are sniffed with [`ConfigFormat::sniff`].
+pub fn from_file<T>(file: impl AsRef<Path>) -> Result<T, ConfigError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let mut path = file.as_ref().to_path_buf();
+    if path.is_dir() {
+        let json = path.join("config.json");
+        path = if json.is_file() {
+            json
+        } else {
+            path.join("config.toml")
+        };
+    }
+    let contents = fs::read_to_string(&path).map_err(|source| ConfigError::Io {
+        path: Some(path.clone()),
+        source,
+    })?;
+
+    let format =
+        ConfigFormat::from_extension(&path).unwrap_or_else(|| ConfigFormat::sniff(&contents));
+    from_str(&contents, format)
+}
+
+/// Parses a configuration held in memory, e.g. embedded in the binary or received over
+/// the wire.
+pub fn from_str<T>(contents: &str, format: ConfigFormat) -> Result<T, ConfigError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    match format {
+        ConfigFormat::Json => serde_json::from_str(contents).map_err(|e| ConfigError::Parse {
+            format,
+            line: Some(e.line()).filter(|&line| line > 0),
+            column: Some(e.column()).filter(|&column| column > 0),
+            message: e.to_string(),
+        }),
+        ConfigFormat::Toml => toml::from_str(contents).map_err(|e| {
+            let (line, column) = match e.span() {
+                Some(span) => {
+                    let (line, column) = line_column(contents, span.start);
+                    (Some(line), Some(column))
+                }
+                None => (None, None),
+            };
+            ConfigError::Parse {
+                format,
+                line,
+                column,
+                message: e.message().to_string(),
+            }
+        }),
+    }
+}
+
+/// Reads a whole configuration from `reader`, see [`from_str`].
+pub fn from_reader<T, R>(mut reader: R, format: ConfigFormat) -> Result<T, ConfigError>
 where
     T: serde::de::DeserializeOwned,
+    R: Read,
 {
-    let toml_str = fs::read_to_string(file).expect("FileException: Something occur file...");
+    let mut contents = String::new();
+    reader
+        .read_to_string(&mut contents)
+        .map_err(|source| ConfigError::Io { path: None, source })?;
+    from_str(&contents, format)
+}
+
+/// 1-based line and column (in characters) of the byte `offset` of `text`.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: Option<PathBuf>,
+        source: std::io::Error,
+    },
+    Parse {
+        format: ConfigFormat,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+    Validation(ValidationError),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "Failed to read config '{}': {}", path.display(), source),
+            ConfigError::Io { path: None, source } => {
+                write!(f, "Failed to read config: {}", source)
+            }
+            ConfigError::Parse {
+                format,
+                line: Some(line),
+                column: Some(column),
+                message,
+            } => write!(
+                f,
+                "Invalid {} config at line {}, column {}: {}",
+                format, line, column, message
+            ),
+            ConfigError::Parse {
+                format, message, ..
+            } => write!(f, "Invalid {} config: {}", format, message),
+            ConfigError::Validation(e) => write!(f, "Invalid config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Parse { .. } => None,
+            ConfigError::Validation(e) => Some(e),
+        }
+    }
+}
+
+impl From<ValidationError> for ConfigError {
+    fn from(e: ValidationError) -> Self {
+        ConfigError::Validation(e)
+    }
+}
+
+/// Hyper-parameters of a phi model, named as in the Hugging Face `config.json`.
+///
+/// Keys missing from a loaded file take the value of microsoft/phi-2; keys we do not
+/// know about are kept in `extra` and written back out on serialisation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhiConfig {
+    #[serde(rename = "_name_or_path")]
+    pub name_or_path: String,
+    pub architectures: Vec<String>,
+    pub model_type: String,
+    pub vocab_size: usize,
+    pub hidden_size: usize,
+    pub intermediate_size: usize,
+    pub num_hidden_layers: usize,
+    pub num_attention_heads: usize,
+    pub num_key_value_heads: Option<usize>,
+    pub hidden_act: String,
+    pub max_position_embeddings: usize,
+    pub layer_norm_eps: f64,
+    pub partial_rotary_factor: f64,
+    pub rope_theta: f64,
+    pub qk_layernorm: bool,
+    pub tie_word_embeddings: bool,
+    pub bos_token_id: usize,
+    pub eos_token_id: usize,
+    pub initializer_range: f64,
+    pub resid_pdrop: f64,
+    pub embd_pdrop: f64,
+    pub attention_dropout: f64,
+    pub use_cache: bool,
+    pub torch_dtype: String,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+impl Default for PhiConfig {
+    /// microsoft/phi-2
+    fn default() -> Self {
+        PhiConfig {
+            name_or_path: "microsoft/phi-2".to_string(),
+            architectures: vec!["PhiForCausalLM".to_string()],
+            model_type: "phi".to_string(),
+            vocab_size: 51200,
+            hidden_size: 2560,
+            intermediate_size: 10240,
+            num_hidden_layers: 32,
+            num_attention_heads: 32,
+            num_key_value_heads: Some(32),
+            hidden_act: "gelu_new".to_string(),
+            max_position_embeddings: 2048,
+            layer_norm_eps: 1e-5,
+            partial_rotary_factor: 0.4,
+            rope_theta: 10000.0,
+            qk_layernorm: false,
+            tie_word_embeddings: false,
+            bos_token_id: 50256,
+            eos_token_id: 50256,
+            initializer_range: 0.02,
+            resid_pdrop: 0.1,
+            embd_pdrop: 0.0,
+            attention_dropout: 0.0,
+            use_cache: true,
+            torch_dtype: "float16".to_string(),
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+impl PhiConfig {
+    /// Reads a config with [`from_file`] and validates it.
+    pub fn load(file: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let config: PhiConfig = from_file(file)?;
+        config.validate()?;
+        Ok(config)
+    }
 
-    toml::from_str(&toml_str)
+    /// Channels per attention head.
+    pub fn head_dim(&self) -> usize {
+        self.hidden_size / self.num_attention_heads
+    }
+
+    /// Leading channels of every head that are rotated by the rotary embedding.
+    pub fn rotary_dim(&self) -> usize {
+        (self.partial_rotary_factor * self.head_dim() as f64) as usize
+    }
+
+    /// Checks that the model described can be built, reporting the first problem found.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        for (field, value) in [
+            ("vocab_size", self.vocab_size),
+            ("hidden_size", self.hidden_size),
+            ("intermediate_size", self.intermediate_size),
+            ("num_hidden_layers", self.num_hidden_layers),
+            ("num_attention_heads", self.num_attention_heads),
+            ("max_position_embeddings", self.max_position_embeddings),
+        ] {
+            if value == 0 {
+                return Err(ValidationError::Zero { field });
+            }
+        }
+        if !self.hidden_size.is_multiple_of(self.num_attention_heads) {
+            return Err(ValidationError::NotDivisible {
+                field: "hidden_size",
+                value: self.hidden_size,
+                divisor_field: "num_attention_heads",
+                divisor: self.num_attention_heads,
+            });
+        }
+        if let Some(kv_heads) = self.num_key_value_heads {
+            if kv_heads != self.num_attention_heads {
+                return Err(ValidationError::Unsupported {
+                    field: "num_key_value_heads",
+                    value: kv_heads.to_string(),
+                });
+            }
+        }
+
+        for (field, value, valid) in [
+            (
+                "partial_rotary_factor",
+                self.partial_rotary_factor,
+                (0.0..=1.0).contains(&self.partial_rotary_factor),
+            ),
+            (
+                "layer_norm_eps",
+                self.layer_norm_eps,
+                self.layer_norm_eps >= 0.0 && self.layer_norm_eps.is_finite(),
+            ),
+            (
+                "rope_theta",
+                self.rope_theta,
+                self.rope_theta > 0.0 && self.rope_theta.is_finite(),
+            ),
+        ] {
+            if !valid {
+                return Err(ValidationError::OutOfRange { field, value });
+            }
+        }
+        if !self.rotary_dim().is_multiple_of(2) {
+            return Err(ValidationError::OddRotaryDim {
+                rotary_dim: self.rotary_dim(),
+            });
+        }
+
+        for (field, token) in [
+            ("bos_token_id", self.bos_token_id),
+            ("eos_token_id", self.eos_token_id),
+        ] {
+            if token >= self.vocab_size {
+                return Err(ValidationError::TokenOutOfVocab {
+                    field,
+                    token,
+                    vocab_size: self.vocab_size,
+                });
+            }
+        }
+
+        if self.hidden_act != "gelu_new" {
+            return Err(ValidationError::Unsupported {
+                field: "hidden_act",
+                value: self.hidden_act.clone(),
+            });
+        }
+        for (field, enabled) in [
+            ("qk_layernorm", self.qk_layernorm),
+            ("tie_word_embeddings", self.tie_word_embeddings),
+        ] {
+            if enabled {
+                return Err(ValidationError::Unsupported {
+                    field,
+                    value: enabled.to_string(),
+                });
+            }
+        }
+        // only plain RoPE is implemented; a scaled one would load and silently run unscaled
+        if let Some(rope_scaling) = self.extra.get("rope_scaling").filter(|v| !v.is_null()) {
+            return Err(ValidationError::Unsupported {
+                field: "rope_scaling",
+                value: rope_scaling.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Parses and validates a TOML or JSON config, see [`ConfigFormat::sniff`].
+impl FromStr for PhiConfig {
+    type Err = ConfigError;
+
+    fn from_str(contents: &str) -> Result<Self, ConfigError> {
+        let config: PhiConfig = from_str(contents, ConfigFormat::sniff(contents))?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Why a [`PhiConfig`] does not describe a model we can build.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    Zero {
+        field: &'static str,
+    },
+    NotDivisible {
+        field: &'static str,
+        value: usize,
+        divisor_field: &'static str,
+        divisor: usize,
+    },
+    OutOfRange {
+        field: &'static str,
+        value: f64,
+    },
+    OddRotaryDim {
+        rotary_dim: usize,
+    },
+    TokenOutOfVocab {
+        field: &'static str,
+        token: usize,
+        vocab_size: usize,
+    },
+    Unsupported {
+        field: &'static str,
+        value: String,
+    },
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::Zero { field } => write!(f, "{} must be greater than 0", field),
+            ValidationError::NotDivisible {
+                field,
+                value,
+                divisor_field,
+                divisor,
+            } => write!(
+                f,
+                "{}={} must be divisible by {}={}",
+                field, value, divisor_field, divisor
+            ),
+            ValidationError::OutOfRange { field, value } => {
+                write!(f, "{}={} is out of range", field, value)
+            }
+            ValidationError::OddRotaryDim { rotary_dim } => write!(
+                f,
+                "partial_rotary_factor gives rotary_dim={}, which must be even",
+                rotary_dim
+            ),
+            ValidationError::TokenOutOfVocab {
+                field,
+                token,
+                vocab_size,
+            } => write!(
+                f,
+                "{}={} is outside the vocabulary of {} tokens",
+                field, token, vocab_size
+            ),
+            ValidationError::Unsupported { field, value } => {
+                write!(f, "{}={} is not supported", field, value)
+            }
+        }
+    }
 }
 
+impl std::error::Error for ValidationError {}
+
 #[cfg(test)]
 mod configuration {
 
     use serde::Deserialize;
 
-    use super::from_file;
+    use super::{
+        from_file, from_reader, from_str, ConfigError, ConfigFormat, PhiConfig, ValidationError,
+    };
 
     #[derive(Deserialize)]
     struct TestConfig {
@@ -27,4 +468,169 @@ mod configuration {
         let config: TestConfig = from_file("models/phi-2/config.toml").unwrap();
         assert_eq!(config._name_or_path, "microsoft/phi-2")
     }
+
+    #[test]
+    fn phi_2_defaults() {
+        let mut config: PhiConfig = from_file("models/phi-2/config.toml").unwrap();
+        assert_eq!(config.extra["transformers_version"], "4.37.0");
+        config.extra.clear();
+        assert_eq!(config, PhiConfig::default());
+        assert_eq!((config.head_dim(), config.rotary_dim()), (80, 32));
+        assert_eq!(config.validate(), Ok(()));
+
+        // missing keys fall back to phi-2
+        let partial: PhiConfig = toml::from_str("num_hidden_layers = 2").unwrap();
+        assert_eq!(partial.num_hidden_layers, 2);
+        assert_eq!(partial.hidden_size, 2560);
+    }
+
+    #[test]
+    fn json_config() {
+        let json: PhiConfig = from_file("models/phi-2/config.json").unwrap();
+        let toml: PhiConfig = from_file("models/phi-2/config.toml").unwrap();
+        // config.json carries `"rope_scaling": null` on top of the TOML keys
+        assert_eq!(json.extra["rope_scaling"], serde_json::Value::Null);
+        assert_eq!(json.validate(), Ok(()));
+        let mut scaled = json.clone();
+        scaled.extra.insert(
+            "rope_scaling".to_string(),
+            serde_json::json!({"type": "linear", "factor": 2.0}),
+        );
+        assert!(matches!(
+            scaled.validate(),
+            Err(ValidationError::Unsupported {
+                field: "rope_scaling",
+                ..
+            })
+        ));
+        assert_eq!(
+            PhiConfig {
+                extra: Default::default(),
+                ..json.clone()
+            },
+            PhiConfig {
+                extra: Default::default(),
+                ..toml
+            }
+        );
+
+        // a model directory resolves to its config.json
+        let dir: PhiConfig = from_file("models/phi-2").unwrap();
+        assert_eq!(dir, json);
+
+        // unknown keys survive a round trip
+        let written = serde_json::to_string(&json).unwrap();
+        let reread: PhiConfig = serde_json::from_str(&written).unwrap();
+        assert_eq!(reread, json);
+        assert!(written.contains("\"transformers_version\":\"4.37.0\""));
+    }
+
+    #[test]
+    fn sniffs_format_without_extension() {
+        let dir = std::env::temp_dir().join(format!("phi-2-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("json"), r#"{"num_hidden_layers": 3}"#).unwrap();
+        std::fs::write(dir.join("toml"), "num_hidden_layers = 4").unwrap();
+        let json: PhiConfig = from_file(dir.join("json")).unwrap();
+        let toml: PhiConfig = from_file(dir.join("toml")).unwrap();
+        assert_eq!((json.num_hidden_layers, toml.num_hidden_layers), (3, 4));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn validation_errors() {
+        let invalid = |edit: fn(&mut PhiConfig)| {
+            let mut config = PhiConfig::default();
+            edit(&mut config);
+            config.validate().unwrap_err()
+        };
+
+        assert_eq!(
+            invalid(|c| c.num_attention_heads = 0),
+            ValidationError::Zero {
+                field: "num_attention_heads"
+            }
+        );
+        assert_eq!(
+            invalid(|c| c.num_attention_heads = 30),
+            ValidationError::NotDivisible {
+                field: "hidden_size",
+                value: 2560,
+                divisor_field: "num_attention_heads",
+                divisor: 30
+            }
+        );
+        assert_eq!(
+            invalid(|c| c.partial_rotary_factor = 1.5),
+            ValidationError::OutOfRange {
+                field: "partial_rotary_factor",
+                value: 1.5
+            }
+        );
+        assert_eq!(
+            invalid(|c| c.partial_rotary_factor = 0.0125),
+            ValidationError::OddRotaryDim { rotary_dim: 1 }
+        );
+        assert_eq!(
+            invalid(|c| c.eos_token_id = 51200),
+            ValidationError::TokenOutOfVocab {
+                field: "eos_token_id",
+                token: 51200,
+                vocab_size: 51200
+            }
+        );
+        assert!(matches!(
+            invalid(|c| c.hidden_act = "relu".to_string()),
+            ValidationError::Unsupported {
+                field: "hidden_act",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn errors_instead_of_panics() {
+        let missing = from_file::<PhiConfig>("models/phi-2/missing.toml").unwrap_err();
+        assert!(matches!(missing, ConfigError::Io { path: Some(_), .. }));
+
+        let toml = "hidden_size = 2560\nvocab_size = \"many\"\n";
+        match from_str::<PhiConfig>(toml, ConfigFormat::Toml).unwrap_err() {
+            ConfigError::Parse {
+                format,
+                line,
+                column,
+                ..
+            } => assert_eq!(
+                (format, line, column),
+                (ConfigFormat::Toml, Some(2), Some(14))
+            ),
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        let json = "{\n  \"hidden_size\": 2560,\n  \"vocab_size\": \"many\"\n}";
+        match from_str::<PhiConfig>(json, ConfigFormat::Json).unwrap_err() {
+            ConfigError::Parse { format, line, .. } => {
+                assert_eq!((format, line), (ConfigFormat::Json, Some(3)))
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        let invalid = "num_attention_heads = 30".parse::<PhiConfig>().unwrap_err();
+        assert!(matches!(
+            invalid,
+            ConfigError::Validation(ValidationError::NotDivisible { .. })
+        ));
+        assert!(invalid.to_string().contains("num_attention_heads=30"));
+    }
+
+    #[test]
+    fn from_reader_and_str() {
+        let json = r#"{"num_hidden_layers": 2, "vocab_size": 51200}"#;
+        let config: PhiConfig = from_reader(json.as_bytes(), ConfigFormat::Json).unwrap();
+        assert_eq!(config.num_hidden_layers, 2);
+        assert_eq!(json.parse::<PhiConfig>().unwrap(), config);
+
+        let loaded = PhiConfig::load("models/phi-2").unwrap();
+        assert_eq!(loaded.name_or_path, "microsoft/phi-2");
+    }
 }
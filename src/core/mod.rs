@@ -1,5 +1,12 @@
+pub(crate) mod config;
 pub(crate) mod device;
-// pub(crate) mod nn;
+pub(crate) mod models;
+pub(crate) mod nn;
+pub(crate) mod safetensors;
+pub(crate) mod tensor;
+pub(crate) mod tokenizer;
 
-mod tensor;
 pub use tensor::Tensor;
+// the public entry point of the tokenizer, not used by the binary itself
+#[allow(unused_imports)]
+pub use tokenizer::Tokenizer;
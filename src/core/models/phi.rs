@@ -0,0 +1,545 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::core::config::PhiConfig;
+use crate::core::nn::activation::Gelu;
+use crate::core::nn::{Embedding, KvCache, LayerNorm, Linear, PhiDecoderLayer};
+use crate::core::safetensors::error::SafeTensorError;
+use crate::core::safetensors::{self, Checkpoint, SafeDtype};
+use crate::core::tensor::error::TensorError;
+use crate::core::tensor::{Float, Tensor, TensorView};
+
+/// Lists the tensor parameters of a [`PhiForCausalLM`] under their Hugging Face names,
+/// borrowed shared or, with a trailing `mut`, mutably.
+macro_rules! named_parameters {
+    ($model:expr $(, $m:tt)?) => {{
+        let model = $model;
+        let mut params = Vec::new();
+        for (i, layer) in (& $($m)? model.layers).into_iter().enumerate() {
+            let norm = & $($m)? layer.input_layernorm;
+            params.push((
+                format!("model.layers.{}.input_layernorm.weight", i),
+                & $($m)? norm.weight,
+            ));
+            params.push((
+                format!("model.layers.{}.input_layernorm.bias", i),
+                & $($m)? norm.bias,
+            ));
+            for (name, linear) in [
+                ("self_attn.q_proj", & $($m)? layer.self_attn.q_proj),
+                ("self_attn.k_proj", & $($m)? layer.self_attn.k_proj),
+                ("self_attn.v_proj", & $($m)? layer.self_attn.v_proj),
+                ("self_attn.dense", & $($m)? layer.self_attn.dense),
+                ("mlp.fc1", & $($m)? layer.mlp.fc1),
+                ("mlp.fc2", & $($m)? layer.mlp.fc2),
+            ] {
+                let prefix = format!("model.layers.{}.{}", i, name);
+                params.push((format!("{}.weight", prefix), & $($m)? linear.weight));
+                if let Some(bias) = & $($m)? linear.bias {
+                    params.push((format!("{}.bias", prefix), bias));
+                }
+            }
+        }
+        let norm = & $($m)? model.final_layernorm;
+        params.push(("model.final_layernorm.weight".to_string(), & $($m)? norm.weight));
+        params.push(("model.final_layernorm.bias".to_string(), & $($m)? norm.bias));
+        let lm_head = & $($m)? model.lm_head;
+        params.push(("lm_head.weight".to_string(), & $($m)? lm_head.weight));
+        if let Some(bias) = & $($m)? lm_head.bias {
+            params.push(("lm_head.bias".to_string(), bias));
+        }
+        params
+    }};
+}
+
+pub struct PhiForCausalLM<Dtype> {
+    pub embed_tokens: Embedding<Dtype>,
+    pub layers: Vec<PhiDecoderLayer<Dtype>>,
+    pub final_layernorm: LayerNorm<Dtype>,
+    pub lm_head: Linear<Dtype>,
+}
+
+/// The phi-2 language model: token embeddings, a stack of [`PhiDecoderLayer`]s, a final
+/// layer norm and an `lm_head` projecting back onto the vocabulary.
+///
+/// Field names follow the Hugging Face checkpoint (`model.embed_tokens`,
+/// `model.layers.N`, `model.final_layernorm`, `lm_head`).
+impl<Dtype> PhiForCausalLM<Dtype>
+where
+    Dtype: Float + Gelu,
+{
+    /// Creates a zero-initialised model.
+    ///
+    /// # Parameters
+    /// - `vocab_size`: Rows of `embed_tokens` and outputs of `lm_head`.
+    /// - `num_hidden_layers`: Number of decoder layers.
+    /// - The remaining parameters are passed to every [`PhiDecoderLayer::new`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        vocab_size: usize,
+        hidden_size: usize,
+        intermediate_size: usize,
+        num_hidden_layers: usize,
+        num_attention_heads: usize,
+        layer_norm_eps: f64,
+        partial_rotary_factor: f64,
+        rope_theta: f64,
+        max_position_embeddings: usize,
+    ) -> Self {
+        let layers = (0..num_hidden_layers)
+            .map(|_| {
+                PhiDecoderLayer::new(
+                    hidden_size,
+                    intermediate_size,
+                    num_attention_heads,
+                    layer_norm_eps,
+                    partial_rotary_factor,
+                    rope_theta,
+                    max_position_embeddings,
+                )
+            })
+            .collect();
+
+        PhiForCausalLM {
+            embed_tokens: Embedding::new(vocab_size, hidden_size),
+            layers,
+            final_layernorm: LayerNorm::new(hidden_size, layer_norm_eps),
+            lm_head: Linear::new(hidden_size, vocab_size, true),
+        }
+    }
+
+    /// Creates a zero-initialised model with the shapes of `config`, which should have
+    /// passed [`PhiConfig::validate`].
+    pub fn from_config(config: &PhiConfig) -> Self {
+        PhiForCausalLM::new(
+            config.vocab_size,
+            config.hidden_size,
+            config.intermediate_size,
+            config.num_hidden_layers,
+            config.num_attention_heads,
+            config.layer_norm_eps,
+            config.partial_rotary_factor,
+            config.rope_theta,
+            config.max_position_embeddings,
+        )
+    }
+
+    pub fn vocab_size(&self) -> usize {
+        self.lm_head.out_features()
+    }
+
+    /// An empty cache for every layer, holding up to `max_seq_len` positions of `batch`
+    /// sequences.
+    pub fn new_cache(&self, batch: usize, max_seq_len: usize) -> Vec<KvCache<Dtype>> {
+        self.layers
+            .iter()
+            .map(|layer| {
+                let attn = &layer.self_attn;
+                KvCache::new(batch, attn.num_heads(), attn.head_dim(), max_seq_len)
+            })
+            .collect()
+    }
+
+    /// Every tensor parameter under its Hugging Face name, e.g.
+    /// `model.layers.0.self_attn.q_proj.weight`. `model.embed_tokens.weight` is not a
+    /// tensor and is handled separately.
+    pub fn named_parameters(&self) -> Vec<(String, &Tensor<Dtype>)> {
+        named_parameters!(self)
+    }
+
+    /// Mutable counterpart of [`Self::named_parameters`].
+    pub fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor<Dtype>)> {
+        named_parameters!(self, mut)
+    }
+
+    /// Views of every weight of the model, `model.embed_tokens.weight` included, keyed
+    /// by Hugging Face name.
+    pub fn state_dict(&self) -> Vec<(String, TensorView<'_, Dtype>)> {
+        let mut state = vec![(
+            "model.embed_tokens.weight".to_string(),
+            self.embed_tokens.weight_view(),
+        )];
+        state.extend(
+            self.named_parameters()
+                .into_iter()
+                .map(|(name, param)| (name, param.view())),
+        );
+        state
+    }
+
+    /// Writes the [`Self::state_dict`] to a single safetensors file, storing every tensor
+    /// as `dtype`.
+    pub fn save_safetensors(
+        &self,
+        path: impl AsRef<Path>,
+        dtype: SafeDtype,
+    ) -> Result<(), SafeTensorError> {
+        let metadata = BTreeMap::from([("format".to_string(), "pt".to_string())]);
+        safetensors::save(path, &self.state_dict(), dtype, &metadata)
+    }
+
+    /// Loads every weight from a safetensors file, a sharded checkpoint's index, or a
+    /// directory holding either, see [`Checkpoint::open`] and [`Self::load_weights`].
+    pub fn load_safetensors(&mut self, path: impl AsRef<Path>) -> Result<(), SafeTensorError> {
+        self.load_weights(&Checkpoint::open(path)?)
+    }
+
+    /// Replaces every parameter with the tensor of the same Hugging Face name in
+    /// `checkpoint`, converting from the stored dtype.
+    ///
+    /// Every tensor is read and checked before any parameter is replaced, so on error the
+    /// model is left exactly as it was.
+    ///
+    /// # Returns
+    /// - `Err(SafeTensorError::MissingTensor)`: If the checkpoint lacks a parameter.
+    /// - `Err(SafeTensorError::Tensor)`: If a tensor does not have the parameter's shape.
+    pub fn load_weights(&mut self, checkpoint: &Checkpoint) -> Result<(), SafeTensorError> {
+        let name = "model.embed_tokens.weight";
+        let expected = vec![
+            self.embed_tokens.num_embeddings(),
+            self.embed_tokens.embedding_dim(),
+        ];
+        let embed_tokens = checked(name, checkpoint.tensor(name)?, expected)?;
+        let tensors = self
+            .named_parameters()
+            .into_iter()
+            .map(|(name, param)| checked(&name, checkpoint.tensor(&name)?, param.shape.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.embed_tokens.weight = embed_tokens.storage;
+        for ((_, param), tensor) in self.named_parameters_mut().into_iter().zip(tensors) {
+            *param = tensor;
+        }
+        Ok(())
+    }
+
+    /// Computes next-token logits for every position of `input_ids`.
+    ///
+    /// `position` is the absolute position of the first token. Without a cache it must be
+    /// 0. With a cache (one [`KvCache`] per layer, see [`Self::new_cache`]) the tokens are
+    /// decoded after the cached prefix: `position` equal to the cached length continues
+    /// the sequence, a smaller one first discards the cached positions from `position`
+    /// on, e.g. to re-generate from an earlier point.
+    ///
+    /// # Parameters
+    /// - `input_ids`: Token ids of shape `(batch, seq_len)`.
+    /// - `position`: Position of `input_ids[.., 0]` in the sequence.
+    /// - `cache`: Optional per-layer key/value caches, updated in place.
+    ///
+    /// # Returns
+    /// - `Ok(Tensor<Dtype>)`: Logits of shape `(batch, seq_len, vocab_size)`.
+    /// - `Err(TensorError::InvalidShape)`: If `input_ids` is not 2-D or the number of
+    ///   caches does not match the number of layers.
+    /// - `Err(TensorError::OutOfBounds)`: If a token id is outside the vocabulary,
+    ///   `position` is past the cached length, or the cache or rotary table is full.
+    pub fn forward(
+        &self,
+        input_ids: &Tensor<usize>,
+        position: usize,
+        cache: Option<&mut [KvCache<Dtype>]>,
+    ) -> Result<Tensor<Dtype>, TensorError> {
+        if input_ids.rank() != 2 {
+            return Err(TensorError::InvalidShape {
+                expected: vec![input_ids.dim(0).unwrap_or(1), input_ids.numel()],
+                found: input_ids.shape.clone(),
+            });
+        }
+
+        let mut hidden_states = self.embed_tokens.embed(input_ids)?;
+        match cache {
+            Some(cache) => {
+                if cache.len() != self.layers.len() {
+                    return Err(TensorError::InvalidShape {
+                        expected: vec![self.layers.len()],
+                        found: vec![cache.len()],
+                    });
+                }
+                for layer_cache in cache.iter_mut() {
+                    layer_cache.truncate(position)?;
+                }
+                for (layer, layer_cache) in self.layers.iter().zip(cache.iter_mut()) {
+                    hidden_states = layer.forward(&hidden_states, None, Some(layer_cache))?;
+                }
+            }
+            None => {
+                if position != 0 {
+                    return Err(TensorError::OutOfBounds { index: position });
+                }
+                for layer in &self.layers {
+                    hidden_states = layer.forward(&hidden_states, None, None)?;
+                }
+            }
+        }
+
+        let hidden_states = self.final_layernorm.forward(&hidden_states)?;
+        self.lm_head.forward(&hidden_states)
+    }
+}
+
+fn checked<Dtype>(
+    name: &str,
+    tensor: Tensor<Dtype>,
+    expected: Vec<usize>,
+) -> Result<Tensor<Dtype>, SafeTensorError> {
+    if tensor.shape != expected {
+        return Err(SafeTensorError::Tensor {
+            name: name.to_string(),
+            source: TensorError::InvalidShape {
+                expected,
+                found: tensor.shape,
+            },
+        });
+    }
+    Ok(tensor)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::PhiForCausalLM;
+    use crate::core::config::PhiConfig;
+    use crate::core::nn::test::assert_close;
+    use crate::core::nn::Linear;
+    use crate::core::safetensors::error::SafeTensorError;
+    use crate::core::safetensors::test::{f32_bytes, serialize, temp_dir};
+    use crate::core::safetensors::{Checkpoint, SafeDtype, SafeTensors};
+    use crate::core::tensor::error::TensorError;
+    use crate::core::Tensor;
+
+    const VOCAB: usize = 11;
+
+    fn randomise(linear: &mut Linear<f32>) {
+        let shape = linear.weight.shape.clone();
+        linear.weight = Tensor::<f32>::rand_f32(shape).sub_scalar(0.5);
+        if let Some(bias) = &mut linear.bias {
+            *bias = Tensor::<f32>::rand_f32(bias.shape.clone()).sub_scalar(0.5);
+        }
+    }
+
+    /// Two layers, 8 wide, 2 heads of 4 channels with half of them rotated.
+    fn tiny_config() -> PhiConfig {
+        PhiConfig {
+            vocab_size: VOCAB,
+            hidden_size: 8,
+            intermediate_size: 16,
+            num_hidden_layers: 2,
+            num_attention_heads: 2,
+            num_key_value_heads: None,
+            partial_rotary_factor: 0.5,
+            max_position_embeddings: 16,
+            bos_token_id: 0,
+            eos_token_id: 0,
+            ..PhiConfig::default()
+        }
+    }
+
+    fn tiny_model() -> PhiForCausalLM<f32> {
+        let config = tiny_config();
+        config.validate().unwrap();
+        let mut model = PhiForCausalLM::<f32>::from_config(&config);
+        model.embed_tokens.weight = Tensor::<f32>::rand_f32([VOCAB * 8]).storage;
+        for layer in &mut model.layers {
+            let attn = &mut layer.self_attn;
+            for linear in [
+                &mut attn.q_proj,
+                &mut attn.k_proj,
+                &mut attn.v_proj,
+                &mut attn.dense,
+                &mut layer.mlp.fc1,
+                &mut layer.mlp.fc2,
+            ] {
+                randomise(linear);
+            }
+            layer.input_layernorm.weight = Tensor::<f32>::rand_f32([8]).add_scalar(0.5);
+        }
+        randomise(&mut model.lm_head);
+        model
+    }
+
+    #[test]
+    fn test_logits_shape() {
+        let model = tiny_model();
+        assert_eq!(model.vocab_size(), VOCAB);
+        let ids = Tensor::from_shape_vec([2, 3], vec![1, 2, 3, 4, 5, 10]).unwrap();
+        let logits = model.forward(&ids, 0, None).unwrap();
+        assert_eq!(logits.shape, vec![2, 3, VOCAB]);
+        assert!(logits.storage.iter().all(|v| v.is_finite()));
+    }
+
+    #[test]
+    fn test_incremental_matches_full() {
+        let model = tiny_model();
+        let tokens = vec![3, 1, 4, 1, 5];
+        let ids = Tensor::from_shape_vec([1, 5], tokens.clone()).unwrap();
+        let full = model.forward(&ids, 0, None).unwrap();
+
+        let mut cache = model.new_cache(1, 16);
+        let prompt = Tensor::from_shape_vec([1, 3], tokens[..3].to_vec()).unwrap();
+        let logits = model.forward(&prompt, 0, Some(&mut cache)).unwrap();
+        assert_close(&logits.storage, &full.storage[..3 * VOCAB], 1e-4);
+        for (t, &token) in tokens.iter().enumerate().skip(3) {
+            let step = Tensor::from_shape_vec([1, 1], vec![token]).unwrap();
+            let logits = model.forward(&step, t, Some(&mut cache)).unwrap();
+            assert_close(
+                &logits.storage,
+                &full.storage[t * VOCAB..(t + 1) * VOCAB],
+                1e-4,
+            );
+        }
+        assert_eq!(cache[0].len(), 5);
+    }
+
+    #[test]
+    fn test_position_rewinds_cache() {
+        let model = tiny_model();
+        let mut cache = model.new_cache(1, 16);
+        let ids = Tensor::from_shape_vec([1, 4], vec![7, 8, 9, 0]).unwrap();
+        model.forward(&ids, 0, Some(&mut cache)).unwrap();
+
+        // re-decode the last two tokens with a different one in between
+        let ids = Tensor::from_shape_vec([1, 2], vec![2, 0]).unwrap();
+        let rewound = model.forward(&ids, 2, Some(&mut cache)).unwrap();
+        let expected = Tensor::from_shape_vec([1, 4], vec![7, 8, 2, 0]).unwrap();
+        let full = model.forward(&expected, 0, None).unwrap();
+        assert_close(&rewound.storage, &full.storage[2 * VOCAB..], 1e-4);
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        let model = tiny_model();
+        let ids = Tensor::from_shape_vec([1, 2], vec![1, VOCAB]).unwrap();
+        assert_eq!(
+            model.forward(&ids, 0, None).unwrap_err(),
+            TensorError::OutOfBounds { index: VOCAB }
+        );
+
+        let ids = Tensor::from_shape_vec([1, 1], vec![1]).unwrap();
+        assert_eq!(
+            model.forward(&ids, 1, None).unwrap_err(),
+            TensorError::OutOfBounds { index: 1 }
+        );
+        let mut cache = model.new_cache(1, 16);
+        assert_eq!(
+            model.forward(&ids, 3, Some(&mut cache)).unwrap_err(),
+            TensorError::OutOfBounds { index: 3 }
+        );
+        assert!(model.forward(&ids, 0, Some(&mut cache[..1])).is_err());
+    }
+
+    #[test]
+    fn test_load_weights() {
+        let mut source = tiny_model();
+        let ids = Tensor::from_shape_vec([1, 3], vec![2, 9, 4]).unwrap();
+        let expected = source.forward(&ids, 0, None).unwrap();
+
+        let embed = f32_bytes(&source.embed_tokens.weight);
+        let mut entries = vec![(
+            "model.embed_tokens.weight".to_string(),
+            vec![VOCAB, 8],
+            embed,
+        )];
+        for (name, param) in source.named_parameters_mut() {
+            entries.push((name, param.shape.clone(), f32_bytes(&param.storage)));
+        }
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|(name, shape, bytes)| (name.as_str(), "F32", shape.clone(), bytes.clone()))
+            .collect();
+        let dir = temp_dir("load-weights");
+        let path = dir.join("model.safetensors");
+        fs::write(&path, serialize(&entries)).unwrap();
+
+        let checkpoint = Checkpoint::open(&dir).unwrap();
+        assert_eq!(checkpoint.len(), 2 * 14 + 5);
+        let mut model = PhiForCausalLM::<f32>::from_config(&tiny_config());
+        model.load_weights(&checkpoint).unwrap();
+        assert_eq!(model.forward(&ids, 0, None).unwrap(), expected);
+
+        // a checkpoint for a narrower model is rejected by shape
+        let mut wide = PhiForCausalLM::<f32>::from_config(&PhiConfig {
+            hidden_size: 16,
+            ..tiny_config()
+        });
+        assert!(matches!(
+            wide.load_weights(&checkpoint),
+            Err(SafeTensorError::Tensor { .. })
+        ));
+        // the third layer is missing, after the embedding and two layers that do match
+        let mut deep = PhiForCausalLM::<f32>::from_config(&PhiConfig {
+            num_hidden_layers: 3,
+            ..tiny_config()
+        });
+        let before = dense_state_dict(&deep);
+        assert!(matches!(
+            deep.load_weights(&checkpoint),
+            Err(SafeTensorError::MissingTensor { .. })
+        ));
+        assert_eq!(dense_state_dict(&deep), before);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn dense_state_dict(model: &PhiForCausalLM<f32>) -> Vec<(String, Tensor<f32>)> {
+        model
+            .state_dict()
+            .into_iter()
+            .map(|(name, view)| (name, view.contiguous()))
+            .collect()
+    }
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let source = tiny_model();
+        let ids = Tensor::from_shape_vec([1, 4], vec![1, 2, 3, 4]).unwrap();
+        let expected = source.forward(&ids, 0, None).unwrap();
+        let dir = temp_dir("round-trip");
+
+        let path = dir.join("f32.safetensors");
+        source.save_safetensors(&path, SafeDtype::F32).unwrap();
+        let mut model = PhiForCausalLM::<f32>::from_config(&tiny_config());
+        model.load_safetensors(&path).unwrap();
+        assert_eq!(model.forward(&ids, 0, None).unwrap(), expected);
+
+        // dtype and shapes are recorded in the header
+        let path = dir.join("bf16.safetensors");
+        source.save_safetensors(&path, SafeDtype::BF16).unwrap();
+        let file = SafeTensors::read(&path).unwrap();
+        assert_eq!(file.metadata()["format"], "pt");
+        for (name, view) in source.state_dict() {
+            let info = file.info(&name).unwrap();
+            assert_eq!((info.dtype, &info.shape), (SafeDtype::BF16, &view.shape));
+        }
+        model.load_safetensors(&path).unwrap();
+        let weight = &model.layers[1].mlp.fc1.weight;
+        let reference = &source.layers[1].mlp.fc1.weight;
+        assert_ne!(weight, reference);
+        for (a, b) in weight.storage.iter().zip(&reference.storage) {
+            assert!((a - b).abs() <= b.abs() / 128.0);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_over_loaded_file() {
+        let ids = Tensor::from_shape_vec([1, 4], vec![1, 2, 3, 4]).unwrap();
+        let dir = temp_dir("save-over-loaded");
+        let path = dir.join("model.safetensors");
+        tiny_model()
+            .save_safetensors(&path, SafeDtype::F32)
+            .unwrap();
+
+        let mut model = PhiForCausalLM::<f32>::from_config(&tiny_config());
+        model.load_safetensors(&path).unwrap();
+        assert_eq!(model.lm_head.weight.storage.is_mapped(), cfg!(unix));
+        let expected = model.forward(&ids, 0, None).unwrap();
+
+        // the weights being written are read from the file being replaced
+        model.save_safetensors(&path, SafeDtype::F32).unwrap();
+        assert_eq!(model.forward(&ids, 0, None).unwrap(), expected);
+        let mut reloaded = PhiForCausalLM::<f32>::from_config(&tiny_config());
+        reloaded.load_safetensors(&path).unwrap();
+        assert_eq!(reloaded.forward(&ids, 0, None).unwrap(), expected);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,3 +1,5 @@
+use crate::core::tensor::reduce::pairwise_sum;
+use crate::core::tensor::{Float, Tensor};
 
 //NOTE: convert these into macros 
 
@@ -49,6 +51,26 @@ pub fn new_gelu_f64(x : f64) -> f64
     0.5 * x * (1.0 + ((2.0 / std::f64::consts::PI).sqrt() * (x + 0.044715 * x.powi(3))).tanh())
 }
 
+/// Element types with a `gelu_new` implementation, so generic layers can use
+/// [`new_gelu_f32`] and [`new_gelu_f64`].
+pub trait Gelu {
+    fn new_gelu(self) -> Self;
+}
+
+impl Gelu for f32 {
+    #[inline]
+    fn new_gelu(self) -> f32 {
+        new_gelu_f32(self)
+    }
+}
+
+impl Gelu for f64 {
+    #[inline]
+    fn new_gelu(self) -> f64 {
+        new_gelu_f64(self)
+    }
+}
+
 pub fn sigmoid_f64(x : f64) -> f64{
     1.0 / (1.0 + (-x).exp())
 }
@@ -59,9 +81,101 @@ pub fn sigmoid_f32(x : f32) -> f32 {
 // TODO: add 16-bit and lower...
 // ADD HERE
 
+/// Applies softmax over the last dimension of `x`.
+///
+/// Each row is shifted by its maximum before exponentiating, so large logits cannot
+/// overflow. Each row is processed in place with separate passes over the output
+/// buffer: one for the maximum, one for the shifted `exp`, a pairwise sum, and one
+/// dividing by that sum. Entries equal to `-inf` (masked positions) get a probability of 0; a
+/// row that is masked entirely comes out as all zeros rather than NaN.
+///
+/// # Examples
+///
+/// ```
+/// let x = Tensor::new([1, 3], vec![1.0f32, 1.0, f32::NEG_INFINITY]).unwrap();
+/// assert_eq!(softmax(&x).storage, vec![0.5, 0.5, 0.0]);
+/// ```
+///
+/// # Parameters
+/// - `x`: A tensor of any rank; rows are taken along its last dimension.
+///
+/// # Returns
+/// - A tensor of the same shape whose rows sum to 1 (or 0 when fully masked).
+pub fn softmax<Dtype: Float>(x: &Tensor<Dtype>) -> Tensor<Dtype>
+{
+    let mut y = x.clone();
+    softmax_inplace(&mut y);
+    y
+}
+
+/// In-place form of [`softmax`], used on freshly computed attention scores.
+pub fn softmax_inplace<Dtype: Float>(x: &mut Tensor<Dtype>)
+{
+    let n = x.shape.last().copied().unwrap_or(1);
+    if n == 0 {
+        return;
+    }
+    for row in x.storage.chunks_exact_mut(n) {
+        let max = row_max(row);
+        if max == Dtype::NEG_INFINITY {
+            row.fill(Dtype::from(0));
+            continue;
+        }
+        for v in row.iter_mut() {
+            *v = (*v - max).exp();
+        }
+        let sum = pairwise_sum(row);
+        for v in row.iter_mut() {
+            *v = *v / sum;
+        }
+    }
+}
+
+/// Applies `log(softmax(x))` over the last dimension of `x`.
+///
+/// Computed as `x - max - log(sum(exp(x - max)))`, which stays finite where taking the
+/// log of a softmax that underflowed to 0 would not. Masked (`-inf`) entries stay
+/// `-inf`, including every entry of a fully masked row.
+///
+/// # Parameters
+/// - `x`: A tensor of any rank; rows are taken along its last dimension.
+///
+/// # Returns
+/// - A tensor of the same shape holding log-probabilities.
+pub fn log_softmax<Dtype: Float>(x: &Tensor<Dtype>) -> Tensor<Dtype>
+{
+    let mut y = x.clone();
+    let n = y.shape.last().copied().unwrap_or(1);
+    if n == 0 {
+        return y;
+    }
+    let mut exps = Vec::with_capacity(n);
+    for row in y.storage.chunks_exact_mut(n) {
+        let max = row_max(row);
+        if max == Dtype::NEG_INFINITY {
+            continue;
+        }
+        exps.clear();
+        exps.extend(row.iter().map(|&v| (v - max).exp()));
+        let log_sum = pairwise_sum(&exps).ln();
+        for v in row.iter_mut() {
+            *v = *v - max - log_sum;
+        }
+    }
+    y
+}
+
+fn row_max<Dtype: Float>(row: &[Dtype]) -> Dtype
+{
+    row.iter()
+        .fold(Dtype::NEG_INFINITY, |m, &v| if v > m { v } else { m })
+}
+
 #[cfg(test)]
 mod test {
-    use super::{new_gelu_f32, new_gelu_f64, sigmoid_f32, sigmoid_f64};
+    use super::{log_softmax, new_gelu_f32, new_gelu_f64, sigmoid_f32, sigmoid_f64, softmax};
+    use crate::core::nn::test::assert_close;
+    use crate::core::tensor::Tensor;
 
 
     #[test]
@@ -76,6 +190,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::legacy_numeric_constants)]
     fn test_sigmoid_f64(){
         assert_eq!(sigmoid_f64(std::f64::MAX), 1.0);
         assert_eq!(sigmoid_f64(std::f64::MIN), 0.0);
@@ -83,9 +198,47 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::legacy_numeric_constants)]
     fn test_sigmoid_f32(){
         assert_eq!(sigmoid_f32(std::f32::MAX), 1.0);
         assert_eq!(sigmoid_f32(std::f32::MIN), 0.0);
         assert_eq!(sigmoid_f32(0.0), 0.5);
     }
+
+    #[test]
+    fn test_softmax_rows(){
+        let x = Tensor::new([2, 3], vec![1.0f32, 2.0, 3.0, 0.0, 0.0, 0.0]).unwrap();
+        let y = softmax(&x);
+        assert_eq!(y.shape, vec![2, 3]);
+        assert_close(&y.storage, &[0.09003057, 0.24472847, 0.66524096, 1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0], 1e-6);
+    }
+
+    #[test]
+    fn test_softmax_large_logits(){
+        let x = Tensor::new([1, 2], vec![1000.0f32, 1000.0]).unwrap();
+        assert_eq!(softmax(&x).storage, vec![0.5, 0.5]);
+        let y = log_softmax(&x);
+        assert_close(&y.storage, &[-std::f32::consts::LN_2, -std::f32::consts::LN_2], 1e-6);
+    }
+
+    #[test]
+    fn test_softmax_masked(){
+        let ninf = f32::NEG_INFINITY;
+        let x = Tensor::new([2, 3], vec![0.0, ninf, 0.0, ninf, ninf, ninf]).unwrap();
+        assert_eq!(softmax(&x).storage, vec![0.5, 0.0, 0.5, 0.0, 0.0, 0.0]);
+
+        let y = log_softmax(&x);
+        assert_close(&y.storage[..1], &[-std::f32::consts::LN_2], 1e-6);
+        assert_eq!(y.storage[1], ninf);
+        assert!(y.storage[3..].iter().all(|&v| v == ninf));
+    }
+
+    #[test]
+    fn test_log_softmax_matches_softmax(){
+        let x = Tensor::new([3, 4], (0..12).map(|v| (v as f64 * 0.7).sin() * 5.0).collect()).unwrap();
+        let expected: Vec<f64> = softmax(&x).storage.iter().map(|p| p.ln()).collect();
+        for (a, b) in log_softmax(&x).storage.iter().zip(&expected) {
+            assert!((a - b).abs() < 1e-12);
+        }
+    }
 }
\ No newline at end of file
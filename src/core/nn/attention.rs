@@ -1,27 +1,358 @@
+use crate::core::nn::activation::softmax_inplace;
+use crate::core::nn::{KvCache, Linear, RotaryEmbedding};
+use crate::core::tensor::error::TensorError;
+use crate::core::tensor::{Float, Tensor, TensorView};
 
+// NOTE is it better to implement this as one long vector or
+// small vector
+pub struct MultiHeadAttention<Dtype> {
+    embed_dim: usize,
+    num_heads: usize,
+    head_dim: usize,
+    pub q_proj: Linear<Dtype>,
+    pub k_proj: Linear<Dtype>,
+    pub v_proj: Linear<Dtype>,
+    pub dense: Linear<Dtype>,
+    pub rotary_emb: Option<RotaryEmbedding<Dtype>>,
+}
+
+/// Multi-head causal self-attention, laid out like phi-2's `PhiAttention`: separate
+/// `q_proj`/`k_proj`/`v_proj` projections with bias, followed by a `dense` output
+/// projection. Positions are encoded by rotating queries and keys with `rotary_emb`.
+impl<Dtype> MultiHeadAttention<Dtype>
+where
+    Dtype: Float,
+{
+    /// Creates a zero-initialised layer splitting `embed_dim` across `num_heads` heads.
+    pub fn new(embed_dim: usize, num_heads: usize) -> Self {
+        assert!(
+            num_heads > 0,
+            "ValueError: num_heads={}, must be greater then 0",
+            num_heads
+        );
+        assert!(
+            embed_dim.is_multiple_of(num_heads),
+            "ValueError: embed_dim={} must be divisible by num_heads={}",
+            embed_dim,
+            num_heads
+        );
+
+        MultiHeadAttention {
+            embed_dim,
+            num_heads,
+            head_dim: embed_dim / num_heads,
+            q_proj: Linear::new(embed_dim, embed_dim, true),
+            k_proj: Linear::new(embed_dim, embed_dim, true),
+            v_proj: Linear::new(embed_dim, embed_dim, true),
+            dense: Linear::new(embed_dim, embed_dim, true),
+            rotary_emb: None,
+        }
+    }
+
+    pub fn num_heads(&self) -> usize {
+        self.num_heads
+    }
+
+    pub fn head_dim(&self) -> usize {
+        self.head_dim
+    }
+
+    /// Attends every position of `x` to itself and the positions before it.
+    ///
+    /// With a `cache`, `x` holds only the positions after the cached ones: their keys
+    /// and values are appended to the cache and the queries attend over the whole
+    /// cached prefix, so incremental decoding never recomputes earlier positions.
+    ///
+    /// # Parameters
+    /// - `x`: Hidden states of shape `(batch, seq_len, embed_dim)`.
+    /// - `attention_mask`: Optional `(batch, kv_len)` padding mask over every key position,
+    ///   cached ones included; key positions whose entry is 0 are never attended to.
+    /// - `cache`: Optional key/value cache of this layer.
+    ///
+    /// # Returns
+    /// - `Ok(Tensor<Dtype>)`: The `(batch, seq_len, embed_dim)` output of `dense`.
+    /// - `Err(TensorError)`: If `x` or the mask have the wrong shape, or the cache is full.
+    ///   The cache is left unchanged on error.
+    pub fn forward(
+        &self,
+        x: &Tensor<Dtype>,
+        attention_mask: Option<&Tensor<Dtype>>,
+        cache: Option<&mut KvCache<Dtype>>,
+    ) -> Result<Tensor<Dtype>, TensorError> {
+        if x.rank() != 3 || x.shape[2] != self.embed_dim {
+            return Err(TensorError::InvalidShape {
+                expected: vec![x.dim(0).unwrap_or(1), x.dim(1).unwrap_or(1), self.embed_dim],
+                found: x.shape.clone(),
+            });
+        }
+        let (batch, seq_len) = (x.shape[0], x.shape[1]);
 
-mod linear;
-use linear::Linear;
+        let offset = cache.as_ref().map_or(0, |c| c.len());
+        // checked before the cache is appended to, so a bad mask cannot advance it
+        if let Some(mask) = attention_mask {
+            let expected = vec![batch, offset + seq_len];
+            if mask.shape != expected {
+                return Err(TensorError::InvalidShape {
+                    expected,
+                    found: mask.shape.clone(),
+                });
+            }
+        }
 
-// NOTE is it better to implement this as one long vector or 
-// small vector 
-struct MultiHeadAttention<T>{
-    k : Linear,
-    q : Linear,
-    v : Linear,
+        let mut q = self.split_heads(&self.q_proj.forward(x)?)?;
+        let mut k = self.split_heads(&self.k_proj.forward(x)?)?;
+        let v = self.split_heads(&self.v_proj.forward(x)?)?;
+        if let Some(rotary_emb) = &self.rotary_emb {
+            q = rotary_emb.forward(&q, offset)?;
+            k = rotary_emb.forward(&k, offset)?;
+        }
+        // the cached prefix is attended to in place, without copying it out of the cache
+        let context = match cache {
+            Some(cache) => {
+                cache.append(&k, &v)?;
+                scaled_dot_product_attention(
+                    &q,
+                    cache.keys(),
+                    cache.values(),
+                    offset,
+                    attention_mask,
+                )?
+            }
+            None => scaled_dot_product_attention(&q, &k, &v, offset, attention_mask)?,
+        };
+
+        // (batch, heads, seq, head_dim) -> (batch, seq, embed_dim)
+        let merged =
+            context
+                .transpose(1, 2)?
+                .contiguous()
+                .into_shape([batch, seq_len, self.embed_dim])?;
+        self.dense.forward(&merged)
+    }
+
+    /// `(batch, seq, embed_dim) -> (batch, heads, seq, head_dim)`
+    fn split_heads(&self, x: &Tensor<Dtype>) -> Result<Tensor<Dtype>, TensorError> {
+        let (batch, seq_len) = (x.shape[0], x.shape[1]);
+        Ok(x.reshape([batch, seq_len, self.num_heads, self.head_dim])?
+            .transpose(1, 2)?
+            .contiguous())
+    }
 }
 
-impl<T> MultiHeadAttention<T>{
+/// `softmax(q k^T / sqrt(head_dim) + mask) v` over `(batch, heads, seq, head_dim)` inputs.
+///
+/// The query at row `i` sits at absolute position `offset + i` and may only attend to
+/// keys at positions `<= offset + i`; `offset` is non-zero when `k` and `v` also hold
+/// earlier, cached positions. Keys whose `attention_mask` entry, of shape
+/// `(batch, kv_len)`, is 0 are excluded as padding. A query with nothing left to attend
+/// to produces zeros instead of NaN.
+///
+/// `k` and `v` may be views, such as the cached prefix of a [`KvCache`]; they are read in
+/// place rather than copied.
+pub fn scaled_dot_product_attention<'kv, Dtype: Float + 'kv>(
+    q: &Tensor<Dtype>,
+    k: impl Into<TensorView<'kv, Dtype>>,
+    v: impl Into<TensorView<'kv, Dtype>>,
+    offset: usize,
+    attention_mask: Option<&Tensor<Dtype>>,
+) -> Result<Tensor<Dtype>, TensorError> {
+    let (k, v) = (k.into(), v.into());
+    let (batch, heads, q_len, head_dim) = (q.dim(0)?, q.dim(1)?, q.dim(2)?, q.dim(3)?);
+    let kv_len = k
+        .shape
+        .get(2)
+        .copied()
+        .ok_or(TensorError::OutOfBounds { index: 2 })?;
+    if let Some(mask) = attention_mask {
+        if mask.shape != [batch, kv_len] {
+            return Err(TensorError::InvalidShape {
+                expected: vec![batch, kv_len],
+                found: mask.shape.clone(),
+            });
+        }
+    }
 
-    fn new(embed_dim: i32, num_heads: i32, dropout: Option<f32>) -> Self 
-        where T: Clone
-    {
-        todo!("NotImplemented: soon will be implemented later")
+    let scale = Dtype::from(1) / Dtype::from_usize(head_dim).sqrt();
+    let mut scores = q.matmul_transposed_(k)?;
+    scores *= scale;
+
+    let zero = Dtype::from(0);
+    // `max(1)`: with no keys there are no scores to mask, but a zero chunk size panics
+    for (row, chunk) in scores.storage.chunks_exact_mut(kv_len.max(1)).enumerate() {
+        let b = row / (heads * q_len);
+        let position = offset + row % q_len;
+        for (j, s) in chunk.iter_mut().enumerate() {
+            let padded = attention_mask.is_some_and(|m| m.storage[b * kv_len + j] == zero);
+            if j > position || padded {
+                *s = Dtype::NEG_INFINITY;
+            }
+        }
     }
+    softmax_inplace(&mut scores);
+
+    scores.matmul_(v)
 }
 
+#[cfg(test)]
+mod test {
+    use super::MultiHeadAttention;
+    use crate::core::nn::test::assert_close;
+    use crate::core::nn::{KvCache, Linear, RotaryEmbedding};
+    use crate::core::tensor::error::TensorError;
+    use crate::core::tensor::Tensor;
+
+    fn identity(n: usize) -> Linear<f32> {
+        let mut linear = Linear::new(n, n, true);
+        let mut w = vec![0.0; n * n];
+        for i in 0..n {
+            w[i * n + i] = 1.0;
+        }
+        linear.weight = Tensor::new([n, n], w).unwrap();
+        linear
+    }
+
+    /// With zero queries/keys every allowed key scores the same, so each position
+    /// averages the values it can see.
+    fn averaging_attention(embed_dim: usize, num_heads: usize) -> MultiHeadAttention<f32> {
+        let mut attn = MultiHeadAttention::<f32>::new(embed_dim, num_heads);
+        attn.v_proj = identity(embed_dim);
+        attn.dense = identity(embed_dim);
+        attn
+    }
+
+    #[test]
+    fn test_causal_average() {
+        let attn = averaging_attention(4, 2);
+        let x = Tensor::new([1, 3, 4], (0..12).map(|v| v as f32).collect()).unwrap();
+        let y = attn.forward(&x, None, None).unwrap();
+        assert_eq!(y.shape, vec![1, 3, 4]);
+        #[rustfmt::skip]
+        let expected = vec![
+            0.0, 1.0, 2.0, 3.0,
+            2.0, 3.0, 4.0, 5.0,
+            4.0, 5.0, 6.0, 7.0,
+        ];
+        assert_close(&y.storage, &expected, 1e-5);
+    }
+
+    #[test]
+    fn test_future_tokens_do_not_leak() {
+        let mut attn = MultiHeadAttention::<f32>::new(4, 2);
+        for proj in [
+            &mut attn.q_proj,
+            &mut attn.k_proj,
+            &mut attn.v_proj,
+            &mut attn.dense,
+        ] {
+            proj.weight = Tensor::<f32>::rand_f32([4, 4]);
+        }
+        let a = Tensor::new([1, 3, 4], (0..12).map(|v| v as f32 * 0.1).collect()).unwrap();
+        let mut b = a.clone();
+        b.storage[8..].copy_from_slice(&[9.0, -9.0, 9.0, -9.0]);
+
+        let ya = attn.forward(&a, None, None).unwrap();
+        let yb = attn.forward(&b, None, None).unwrap();
+        assert_eq!(ya.storage[..8], yb.storage[..8]);
+        assert_ne!(ya.storage[8..], yb.storage[8..]);
+    }
+
+    #[test]
+    fn test_padding_mask() {
+        let attn = averaging_attention(2, 1);
+        let x = Tensor::new([2, 2, 2], vec![1.0, 2.0, 3.0, 4.0, 1.0, 2.0, 3.0, 4.0]).unwrap();
+        // the second sequence is left-padded
+        let mask = Tensor::new([2, 2], vec![1.0, 1.0, 0.0, 1.0]).unwrap();
+        let y = attn.forward(&x, Some(&mask), None).unwrap();
+        assert_eq!(y.storage[..4], [1.0, 2.0, 2.0, 3.0]);
+        // the padded query sees nothing and yields zeros (plus bias) rather than NaN
+        assert_eq!(y.storage[4..], [0.0, 0.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_rotary_keeps_causal_average() {
+        // zero queries/keys stay zero under rotation, so the average is unchanged
+        let mut attn = averaging_attention(4, 1);
+        attn.rotary_emb = Some(RotaryEmbedding::new(4, 0.5, 10_000.0, 8));
+        let x = Tensor::new([1, 2, 4], vec![0.0, 1.0, 2.0, 3.0, 2.0, 3.0, 4.0, 5.0]).unwrap();
+        let y = attn.forward(&x, None, None).unwrap();
+        assert_eq!(y.storage, vec![0.0, 1.0, 2.0, 3.0, 1.0, 2.0, 3.0, 4.0]);
+    }
 
+    #[test]
+    fn test_incremental_decoding_matches_full_forward() {
+        let mut attn = MultiHeadAttention::<f32>::new(8, 2);
+        for proj in [
+            &mut attn.q_proj,
+            &mut attn.k_proj,
+            &mut attn.v_proj,
+            &mut attn.dense,
+        ] {
+            proj.weight = Tensor::<f32>::rand_f32([8, 8]);
+        }
+        attn.rotary_emb = Some(RotaryEmbedding::new(4, 0.5, 10_000.0, 16));
 
+        let x = Tensor::new([1, 5, 8], (0..40).map(|v| (v as f32 * 0.3).cos()).collect()).unwrap();
+        let full = attn.forward(&x, None, None).unwrap();
 
+        let mut cache = KvCache::new(1, 2, 4, 16);
+        let prompt = x.narrow(1, 0, 3).unwrap().contiguous();
+        let mut outputs = attn
+            .forward(&prompt, None, Some(&mut cache))
+            .unwrap()
+            .storage
+            .into_vec();
+        for t in 3..5 {
+            let token = x.narrow(1, t, 1).unwrap().contiguous();
+            outputs.extend_from_slice(
+                &attn
+                    .forward(&token, None, Some(&mut cache))
+                    .unwrap()
+                    .storage,
+            );
+        }
+        assert_eq!(cache.len(), 5);
+        assert_close(&outputs, &full.storage, 1e-5);
+    }
 
+    #[test]
+    fn test_empty_sequence() {
+        let attn = averaging_attention(4, 2);
+        let x = Tensor::new([1, 0, 4], vec![]).unwrap();
+        assert_eq!(attn.forward(&x, None, None).unwrap().shape, vec![1, 0, 4]);
+        let mut cache = KvCache::new(1, 2, 2, 4);
+        assert_eq!(
+            attn.forward(&x, None, Some(&mut cache)).unwrap().shape,
+            vec![1, 0, 4]
+        );
+        assert!(cache.is_empty());
+    }
 
+    #[test]
+    fn test_shape_errors() {
+        let attn = MultiHeadAttention::<f32>::new(4, 2);
+        let x = Tensor::new([1, 2, 3], vec![0.0; 6]).unwrap();
+        assert!(attn.forward(&x, None, None).is_err());
+        let x = Tensor::new([1, 2, 4], vec![0.0; 8]).unwrap();
+        let mask = Tensor::new([1, 3], vec![1.0; 3]).unwrap();
+        assert!(attn.forward(&x, Some(&mask), None).is_err());
+    }
+
+    #[test]
+    fn test_failed_forward_leaves_cache_unchanged() {
+        let attn = averaging_attention(4, 2);
+        let mut cache = KvCache::new(1, 2, 2, 8);
+        let x = Tensor::new([1, 2, 4], (0..8).map(|v| v as f32).collect()).unwrap();
+        attn.forward(&x, None, Some(&mut cache)).unwrap();
+
+        // the mask must cover the 2 cached positions as well as the 2 new ones
+        let mask = Tensor::new([1, 2], vec![1.0; 2]).unwrap();
+        assert!(matches!(
+            attn.forward(&x, Some(&mask), Some(&mut cache)),
+            Err(TensorError::InvalidShape { .. })
+        ));
+        assert_eq!(cache.len(), 2);
+        let x = Tensor::new([1, 7, 4], vec![0.0; 28]).unwrap();
+        assert!(attn.forward(&x, None, Some(&mut cache)).is_err());
+        assert_eq!(cache.len(), 2);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::core::nn::activation::Gelu;
+use crate::core::nn::{KvCache, LayerNorm, Linear, MultiHeadAttention, RotaryEmbedding};
+use crate::core::tensor::error::TensorError;
+use crate::core::tensor::{Float, Tensor};
+
+pub struct PhiMLP<Dtype> {
+    pub fc1: Linear<Dtype>,
+    pub fc2: Linear<Dtype>,
+}
+
+/// Feed-forward block, `fc2(new_gelu(fc1(x)))`.
+impl<Dtype> PhiMLP<Dtype>
+where
+    Dtype: Float + Gelu,
+{
+    pub fn new(hidden_size: usize, intermediate_size: usize) -> Self {
+        PhiMLP {
+            fc1: Linear::new(hidden_size, intermediate_size, true),
+            fc2: Linear::new(intermediate_size, hidden_size, true),
+        }
+    }
+
+    pub fn forward(&self, x: &Tensor<Dtype>) -> Result<Tensor<Dtype>, TensorError> {
+        let mut h = self.fc1.forward(x)?;
+        h.map_inplace(Gelu::new_gelu);
+        self.fc2.forward(&h)
+    }
+}
+
+pub struct PhiDecoderLayer<Dtype> {
+    pub input_layernorm: LayerNorm<Dtype>,
+    pub self_attn: MultiHeadAttention<Dtype>,
+    pub mlp: PhiMLP<Dtype>,
+}
+
+/// One phi-2 transformer block.
+///
+/// Unlike the sequential pre-norm block of GPT-2 or LLaMA, attention and the MLP run in
+/// parallel on the same normalised input and both are added to the residual:
+///
+/// ```text
+/// h = input_layernorm(x)
+/// y = x + self_attn(h) + mlp(h)
+/// ```
+impl<Dtype> PhiDecoderLayer<Dtype>
+where
+    Dtype: Float + Gelu,
+{
+    /// Creates a zero-initialised block.
+    ///
+    /// # Parameters
+    /// - `hidden_size`: Width of the residual stream.
+    /// - `intermediate_size`: Width of the MLP hidden layer.
+    /// - `num_attention_heads`: Attention heads; must divide `hidden_size`.
+    /// - `layer_norm_eps`: Epsilon of `input_layernorm`.
+    /// - `partial_rotary_factor`, `rope_theta`, `max_position_embeddings`: Rotary
+    ///   embedding settings, see [`RotaryEmbedding::new`].
+    pub fn new(
+        hidden_size: usize,
+        intermediate_size: usize,
+        num_attention_heads: usize,
+        layer_norm_eps: f64,
+        partial_rotary_factor: f64,
+        rope_theta: f64,
+        max_position_embeddings: usize,
+    ) -> Self {
+        let mut self_attn = MultiHeadAttention::new(hidden_size, num_attention_heads);
+        self_attn.rotary_emb = Some(RotaryEmbedding::new(
+            self_attn.head_dim(),
+            partial_rotary_factor,
+            rope_theta,
+            max_position_embeddings,
+        ));
+
+        PhiDecoderLayer {
+            input_layernorm: LayerNorm::new(hidden_size, layer_norm_eps),
+            self_attn,
+            mlp: PhiMLP::new(hidden_size, intermediate_size),
+        }
+    }
+
+    /// Runs the block over `(batch, seq_len, hidden_size)` hidden states.
+    ///
+    /// `attention_mask` and `cache` are passed through to
+    /// [`MultiHeadAttention::forward`].
+    pub fn forward(
+        &self,
+        hidden_states: &Tensor<Dtype>,
+        attention_mask: Option<&Tensor<Dtype>>,
+        cache: Option<&mut KvCache<Dtype>>,
+    ) -> Result<Tensor<Dtype>, TensorError> {
+        let h = self.input_layernorm.forward(hidden_states)?;
+        let attn_output = self.self_attn.forward(&h, attention_mask, cache)?;
+        let feed_forward = self.mlp.forward(&h)?;
+        Ok(attn_output + &feed_forward + hidden_states)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PhiDecoderLayer, PhiMLP};
+    use crate::core::nn::activation::new_gelu_f32;
+    use crate::core::nn::KvCache;
+    use crate::core::tensor::Tensor;
+
+    #[test]
+    fn test_mlp() {
+        let mut mlp = PhiMLP::<f32>::new(2, 3);
+        mlp.fc1.weight = Tensor::new([3, 2], vec![1.0, 0.0, 0.0, 1.0, 1.0, 1.0]).unwrap();
+        mlp.fc2.weight = Tensor::new([2, 3], vec![1.0, 0.0, 0.0, 0.0, 1.0, 1.0]).unwrap();
+        let x = Tensor::new([1, 2], vec![0.5, -1.0]).unwrap();
+        let y = mlp.forward(&x).unwrap();
+        let expected = [new_gelu_f32(0.5), new_gelu_f32(-1.0) + new_gelu_f32(-0.5)];
+        assert_eq!(y.storage, expected);
+    }
+
+    #[test]
+    fn test_zero_block_is_identity() {
+        // with zero weights and biases both branches output 0, leaving the residual
+        let layer = PhiDecoderLayer::<f32>::new(8, 16, 2, 1e-5, 0.5, 10_000.0, 32);
+        let x = Tensor::new([2, 3, 8], (0..48).map(|v| v as f32).collect()).unwrap();
+        assert_eq!(layer.forward(&x, None, None).unwrap(), x);
+    }
+
+    #[test]
+    fn test_parallel_residual() {
+        let mut layer = PhiDecoderLayer::<f32>::new(4, 8, 2, 1e-5, 1.0, 10_000.0, 32);
+        layer.self_attn.dense.bias = Some(Tensor::new([4], vec![1.0, 2.0, 3.0, 4.0]).unwrap());
+        layer.mlp.fc2.bias = Some(Tensor::new([4], vec![10.0, 20.0, 30.0, 40.0]).unwrap());
+        let x = Tensor::new([1, 2, 4], vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]).unwrap();
+        let y = layer.forward(&x, None, None).unwrap();
+        assert_eq!(
+            y.storage,
+            vec![11.0, 23.0, 35.0, 47.0, 15.0, 27.0, 39.0, 51.0]
+        );
+    }
+
+    #[test]
+    fn test_cache_is_threaded_through() {
+        let layer = PhiDecoderLayer::<f32>::new(4, 8, 2, 1e-5, 1.0, 10_000.0, 32);
+        let mut cache = KvCache::new(1, 2, 2, 32);
+        let x = Tensor::new([1, 3, 4], vec![0.5; 12]).unwrap();
+        layer.forward(&x, None, Some(&mut cache)).unwrap();
+        assert_eq!(cache.len(), 3);
+    }
+}
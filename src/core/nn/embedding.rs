@@ -1,23 +1,23 @@
 use serde::{Deserialize, Serialize};
-use std::{ops::Index, usize};
+use std::ops::Index;
 
-#[path = "../tensor/mod.rs"]
-mod tensor;
-use crate::tensor::Tensor;
+use crate::core::tensor::error::TensorError;
+use crate::core::tensor::{Storage, TensorView};
+use crate::core::Tensor;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Embedding<Dtype> {
     num_embeddings: usize,
     embedding_dim: usize,
-    weight: Tensor<Dtype>,
+    pub(crate) weight: Storage<Dtype>,
 }
 
-///
+/// A lookup table mapping token indices to dense vectors of size `embedding_dim`.
 impl<Dtype> Embedding<Dtype>
 where
     Dtype: Copy + Default,
 {
-    ///
+    /// Creates a zero-initialised `(num_embeddings, embedding_dim)` table.
     pub(crate) fn new(num_embeddings: usize, embedding_dim: usize) -> Self {
         assert!(
             num_embeddings > 0,
@@ -27,16 +27,52 @@ where
             embedding_dim > 0,
             "ValueError: embedding_dim must be larger then 0, try a i32 larger then 0."
         );
-        let weight = vec![Dtype::default(); embedding_dim * num_embeddings];
+        let weight = vec![Dtype::default(); embedding_dim * num_embeddings].into();
 
         Embedding {
             num_embeddings,
             embedding_dim,
-            weight: weight,
+            weight,
         }
     }
 
+    pub fn num_embeddings(&self) -> usize {
+        self.num_embeddings
+    }
+
+    pub fn embedding_dim(&self) -> usize {
+        self.embedding_dim
+    }
+
+    /// The `(num_embeddings, embedding_dim)` table as a tensor view.
+    pub fn weight_view(&self) -> TensorView<'_, Dtype> {
+        TensorView::new(
+            &self.weight,
+            vec![self.num_embeddings, self.embedding_dim],
+            vec![self.embedding_dim, 1],
+        )
+    }
+
+    /// Looks up every index of `ids`, appending `embedding_dim` to its shape, so
+    /// `(batch, seq_len)` token ids become `(batch, seq_len, embedding_dim)` vectors.
     ///
+    /// # Returns
+    /// - `Ok(Tensor<Dtype>)`: The gathered rows.
+    /// - `Err(TensorError::OutOfBounds)`: If an index is not below `num_embeddings`.
+    pub fn embed(&self, ids: &Tensor<usize>) -> Result<Tensor<Dtype>, TensorError> {
+        let mut storage = Vec::with_capacity(ids.numel() * self.embedding_dim);
+        for &idx in &ids.storage {
+            if idx >= self.num_embeddings {
+                return Err(TensorError::OutOfBounds { index: idx });
+            }
+            storage.extend_from_slice(&self[idx]);
+        }
+        let mut shape = ids.shape.clone();
+        shape.push(self.embedding_dim);
+        Tensor::from_shape_vec(shape, storage)
+    }
+
+    /// Gathers the rows selected by `x` into one flat sequence.
     pub(crate) fn forward(&self, x: &[usize]) -> Vec<&Dtype> {
         x.iter()
             .flat_map(|&idx| {
@@ -47,8 +83,7 @@ where
             .collect::<Vec<&Dtype>>()
     }
 
-    ///
-    ///
+    /// Serialises the layer, weights included, into a TOML string.
     pub(crate) fn to_toml(&self) -> String
     where
         Dtype: Serialize,
@@ -71,6 +106,8 @@ impl<Dtype> Index<usize> for Embedding<Dtype> {
 #[cfg(test)]
 mod test {
     use super::Embedding;
+    use crate::core::tensor::error::TensorError;
+    use crate::core::Tensor;
 
     #[test]
     fn test_embedding_new() {
@@ -91,6 +128,22 @@ mod test {
         assert_eq!(y, emb[0]);
     }
 
+    #[test]
+    fn test_embed() {
+        let mut emb = Embedding::<f32>::new(3, 2);
+        emb.weight = vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0].into();
+        let ids = Tensor::from_shape_vec([2, 2], vec![2, 0, 1, 1]).unwrap();
+        let y = emb.embed(&ids).unwrap();
+        assert_eq!(y.shape, vec![2, 2, 2]);
+        assert_eq!(y.storage, vec![4.0, 5.0, 0.0, 1.0, 2.0, 3.0, 2.0, 3.0]);
+
+        let bad = Tensor::from_shape_vec([1], vec![3]).unwrap();
+        assert_eq!(
+            emb.embed(&bad).unwrap_err(),
+            TensorError::OutOfBounds { index: 3 }
+        );
+    }
+
     #[test]
     fn test_forward_pass() {
         let emb = Embedding::<f32>::new(1, 1);
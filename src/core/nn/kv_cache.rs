@@ -0,0 +1,196 @@
+use std::mem::size_of;
+
+use crate::core::tensor::error::TensorError;
+use crate::core::tensor::{Float, Tensor, TensorView};
+
+pub struct KvCache<Dtype> {
+    batch: usize,
+    num_heads: usize,
+    head_dim: usize,
+    max_seq_len: usize,
+    len: usize,
+    // (batch, num_heads, max_seq_len, head_dim), filled up to `len` along the sequence
+    key: Tensor<Dtype>,
+    value: Tensor<Dtype>,
+}
+
+/// Keys and values of one attention layer for the positions decoded so far.
+///
+/// Both buffers are allocated up front for `max_seq_len` positions, so appending a token
+/// during generation is a copy into place and never a reallocation.
+impl<Dtype> KvCache<Dtype>
+where
+    Dtype: Float,
+{
+    pub fn new(batch: usize, num_heads: usize, head_dim: usize, max_seq_len: usize) -> Self {
+        let shape = [batch, num_heads, max_seq_len, head_dim];
+        KvCache {
+            batch,
+            num_heads,
+            head_dim,
+            max_seq_len,
+            len: 0,
+            key: Tensor::zeros(shape),
+            value: Tensor::zeros(shape),
+        }
+    }
+
+    /// Number of cached positions, which is also the position of the next token.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Maximum number of positions the cache can hold.
+    pub fn capacity(&self) -> usize {
+        self.max_seq_len
+    }
+
+    /// Appends `key` and `value`, both `(batch, num_heads, seq_len, head_dim)`, after the
+    /// cached positions.
+    pub fn append(
+        &mut self,
+        key: &Tensor<Dtype>,
+        value: &Tensor<Dtype>,
+    ) -> Result<(), TensorError> {
+        let seq_len = key.dim(2).unwrap_or(0);
+        let expected = vec![self.batch, self.num_heads, seq_len, self.head_dim];
+        for t in [key, value] {
+            if t.shape != expected {
+                return Err(TensorError::InvalidShape {
+                    expected,
+                    found: t.shape.clone(),
+                });
+            }
+        }
+        if self.len + seq_len > self.max_seq_len {
+            return Err(TensorError::OutOfBounds {
+                index: self.len + seq_len - 1,
+            });
+        }
+
+        if seq_len == 0 {
+            return Ok(());
+        }
+
+        let row = self.head_dim;
+        for (dst, src) in [(&mut self.key, key), (&mut self.value, value)] {
+            for (bh, chunk) in src.storage.chunks_exact(seq_len * row).enumerate() {
+                let start = (bh * self.max_seq_len + self.len) * row;
+                dst.storage[start..start + chunk.len()].copy_from_slice(chunk);
+            }
+        }
+        self.len += seq_len;
+        Ok(())
+    }
+
+    /// Cached keys as a `(batch, num_heads, len, head_dim)` view.
+    pub fn keys(&self) -> TensorView<'_, Dtype> {
+        self.key.narrow(2, 0, self.len).unwrap()
+    }
+
+    /// Cached values as a `(batch, num_heads, len, head_dim)` view.
+    pub fn values(&self) -> TensorView<'_, Dtype> {
+        self.value.narrow(2, 0, self.len).unwrap()
+    }
+
+    /// Rolls the cache back so that only positions `0..position` remain, e.g. to reject
+    /// speculative tokens or re-generate from an earlier point.
+    pub fn truncate(&mut self, position: usize) -> Result<(), TensorError> {
+        if position > self.len {
+            return Err(TensorError::OutOfBounds { index: position });
+        }
+        self.len = position;
+        Ok(())
+    }
+
+    /// Forgets every cached position, keeping the allocation.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Bytes held by the preallocated key and value buffers.
+    pub fn allocated_bytes(&self) -> usize {
+        (self.key.numel() + self.value.numel()) * size_of::<Dtype>()
+    }
+
+    /// Bytes of the buffers currently holding cached positions.
+    pub fn used_bytes(&self) -> usize {
+        2 * self.batch * self.num_heads * self.len * self.head_dim * size_of::<Dtype>()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::KvCache;
+    use crate::core::tensor::error::TensorError;
+    use crate::core::tensor::Tensor;
+
+    fn step(start: usize, seq_len: usize) -> Tensor<f32> {
+        // (1, 2, seq_len, 2), each value encoding (head, position, channel)
+        let mut data = Vec::new();
+        for h in 0..2 {
+            for t in start..start + seq_len {
+                data.extend([(h * 100 + t * 10) as f32, (h * 100 + t * 10 + 1) as f32]);
+            }
+        }
+        Tensor::new([1, 2, seq_len, 2], data).unwrap()
+    }
+
+    #[test]
+    fn test_append_and_read() {
+        let mut cache = KvCache::<f32>::new(1, 2, 2, 8);
+        assert!(cache.is_empty());
+        cache.append(&step(0, 3), &step(0, 3)).unwrap();
+        cache.append(&step(3, 1), &step(3, 1)).unwrap();
+        assert_eq!(cache.len(), 4);
+
+        let keys = cache.keys().contiguous();
+        assert_eq!(keys.shape, vec![1, 2, 4, 2]);
+        assert_eq!(keys, step(0, 4));
+        assert_eq!(cache.values().contiguous(), step(0, 4));
+    }
+
+    #[test]
+    fn test_truncate_and_overwrite() {
+        let mut cache = KvCache::<f32>::new(1, 2, 2, 8);
+        cache.append(&step(0, 4), &step(0, 4)).unwrap();
+        cache.truncate(2).unwrap();
+        assert_eq!(cache.keys().contiguous(), step(0, 2));
+
+        cache.append(&step(2, 1), &step(2, 1)).unwrap();
+        assert_eq!(cache.keys().contiguous(), step(0, 3));
+        assert_eq!(
+            cache.truncate(5).unwrap_err(),
+            TensorError::OutOfBounds { index: 5 }
+        );
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_capacity_and_shape_errors() {
+        let mut cache = KvCache::<f32>::new(1, 2, 2, 4);
+        cache.append(&step(0, 3), &step(0, 3)).unwrap();
+        assert_eq!(
+            cache.append(&step(3, 2), &step(3, 2)).unwrap_err(),
+            TensorError::OutOfBounds { index: 4 }
+        );
+        let wrong = Tensor::new([1, 1, 1, 2], vec![0.0; 2]).unwrap();
+        assert!(cache.append(&wrong, &wrong).is_err());
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn test_memory_accounting() {
+        let mut cache = KvCache::<f32>::new(2, 4, 8, 16);
+        assert_eq!(cache.allocated_bytes(), 2 * 2 * 4 * 16 * 8 * 4);
+        assert_eq!(cache.used_bytes(), 0);
+        let kv = Tensor::zeros([2, 4, 3, 8]);
+        cache.append(&kv, &kv).unwrap();
+        assert_eq!(cache.used_bytes(), 2 * 2 * 4 * 3 * 8 * 4);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::core::tensor::error::TensorError;
+use crate::core::tensor::reduce::mean_var;
+use crate::core::tensor::{Float, Tensor};
+
+pub struct LayerNorm<Dtype> {
+    normalized_shape: usize,
+    eps: Dtype,
+    pub weight: Tensor<Dtype>,
+    pub bias: Tensor<Dtype>,
+}
+
+/// Normalises the last dimension to zero mean and unit variance, then applies a learned
+/// elementwise scale (`weight`) and shift (`bias`).
+impl<Dtype> LayerNorm<Dtype>
+where
+    Dtype: Float,
+{
+    /// Creates a layer over a trailing dimension of size `normalized_shape`, with the
+    /// weight set to ones and the bias to zeros. `eps` is added to the variance, phi-2
+    /// reads it from `layer_norm_eps`.
+    pub fn new(normalized_shape: usize, eps: f64) -> Self {
+        assert!(
+            normalized_shape > 0,
+            "ValueError: normalized_shape={}, must be greater then 0",
+            normalized_shape
+        );
+        assert!(eps >= 0.0, "ValueError: eps={}, must not be negative", eps);
+
+        LayerNorm {
+            normalized_shape,
+            eps: Dtype::from_f64(eps),
+            weight: Tensor::ones([normalized_shape]),
+            bias: Tensor::zeros([normalized_shape]),
+        }
+    }
+
+    /// Normalises every `(*, normalized_shape)` row of `x`.
+    ///
+    /// `y = (x - E[x]) / sqrt(Var[x] + eps) * weight + bias`, with the biased variance
+    /// as in PyTorch.
+    ///
+    /// Fails with `InvalidShape` if the last dimension of `x`, or the length of `weight`
+    /// or `bias`, is not `normalized_shape`.
+    pub fn forward(&self, x: &Tensor<Dtype>) -> Result<Tensor<Dtype>, TensorError> {
+        if x.shape.last() != Some(&self.normalized_shape) {
+            let mut expected = x.shape.clone();
+            match expected.last_mut() {
+                Some(last) => *last = self.normalized_shape,
+                None => expected.push(self.normalized_shape),
+            }
+            return Err(TensorError::InvalidShape {
+                expected,
+                found: x.shape.clone(),
+            });
+        }
+
+        for parameter in [&self.weight, &self.bias] {
+            if parameter.shape != [self.normalized_shape] {
+                return Err(TensorError::InvalidShape {
+                    expected: vec![self.normalized_shape],
+                    found: parameter.shape.clone(),
+                });
+            }
+        }
+
+        let (weight, bias) = (&self.weight.storage, &self.bias.storage);
+        let mut y = x.clone();
+        for row in y.storage.chunks_exact_mut(self.normalized_shape) {
+            let (mean, var) = mean_var(row);
+            let inv_std = Dtype::from(1) / (var + self.eps).sqrt();
+            for ((v, &w), &b) in row.iter_mut().zip(weight).zip(bias) {
+                *v = (*v - mean) * inv_std * w + b;
+            }
+        }
+        Ok(y)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LayerNorm;
+    use crate::core::nn::test::assert_close;
+    use crate::core::tensor::error::TensorError;
+    use crate::core::tensor::Tensor;
+
+    #[test]
+    fn test_layer_norm_default_affine() {
+        let ln = LayerNorm::<f32>::new(4, 0.0);
+        // mean 2.5, variance 1.25
+        let x = Tensor::new([1, 4], vec![1.0, 2.0, 3.0, 4.0]).unwrap();
+        let y = ln.forward(&x).unwrap();
+        let s = 1.25f32.sqrt();
+        assert_close(&y.storage, &[-1.5 / s, -0.5 / s, 0.5 / s, 1.5 / s], 1e-5);
+    }
+
+    #[test]
+    fn test_layer_norm_weight_bias_eps() {
+        let mut ln = LayerNorm::<f32>::new(2, 1e-5);
+        ln.weight = Tensor::new([2], vec![2.0, 3.0]).unwrap();
+        ln.bias = Tensor::new([2], vec![0.5, -0.5]).unwrap();
+        // each row has mean 0 +- 1 offset and variance 1, so x_hat = [-1, 1]
+        let x = Tensor::new([2, 1, 2], vec![-1.0, 1.0, 4.0, 6.0]).unwrap();
+        let y = ln.forward(&x).unwrap();
+        assert_eq!(y.shape, vec![2, 1, 2]);
+        let k = 1.0 / (1.0f32 + 1e-5).sqrt();
+        assert_close(
+            &y.storage,
+            &[-2.0 * k + 0.5, 3.0 * k - 0.5, -2.0 * k + 0.5, 3.0 * k - 0.5],
+            1e-5,
+        );
+    }
+
+    #[test]
+    fn test_layer_norm_constant_row() {
+        let ln = LayerNorm::<f64>::new(3, 1e-5);
+        let x = Tensor::new([3], vec![7.0, 7.0, 7.0]).unwrap();
+        assert_eq!(ln.forward(&x).unwrap().storage, vec![0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_layer_norm_shape_mismatch() {
+        let ln = LayerNorm::<f32>::new(4, 1e-5);
+        let x = Tensor::new([2, 3], vec![0.0; 6]).unwrap();
+        assert_eq!(
+            ln.forward(&x).unwrap_err(),
+            TensorError::InvalidShape {
+                expected: vec![2, 4],
+                found: vec![2, 3]
+            }
+        );
+
+        // a weight or bias of the wrong length is an error, not a partly normalised row
+        let mut ln = LayerNorm::<f32>::new(4, 1e-5);
+        ln.weight = Tensor::ones([3]);
+        let x = Tensor::new([1, 4], vec![1.0, 2.0, 3.0, 4.0]).unwrap();
+        assert_eq!(
+            ln.forward(&x).unwrap_err(),
+            TensorError::InvalidShape {
+                expected: vec![4],
+                found: vec![3]
+            }
+        );
+        ln.weight = Tensor::ones([4]);
+        ln.bias = Tensor::zeros([2, 2]);
+        assert!(ln.forward(&x).is_err());
+    }
+}
@@ -1,19 +1,26 @@
-use crate::tensor::Tensor;
+use std::ops::{Add, Div, Mul, Sub};
+
+use crate::core::tensor::error::TensorError;
+use crate::core::Tensor;
 
 pub struct Linear<Dtype> {
     in_features: usize,
     out_features: usize,
     pub weight: Tensor<Dtype>,
-    bias: Option<Vec<Dtype>>,
+    pub bias: Option<Tensor<Dtype>>,
 }
 
-///
-///
+/// Applies an affine transformation `y = xW^T + b` to the incoming data.
 impl<Dtype> Linear<Dtype>
 where
-    Dtype: Clone + Default,
+    Dtype: From<u8>
+        + Copy
+        + Add<Output = Dtype>
+        + Sub<Output = Dtype>
+        + Mul<Output = Dtype>
+        + Div<Output = Dtype>,
 {
-    ///
+    /// Creates a zero-initialised layer, with a bias vector when `bias` is set.
     pub fn new(in_features: usize, out_features: usize, bias: bool) -> Self {
         assert!(
             in_features > 0,
@@ -26,13 +33,12 @@ where
             out_features
         );
 
-        // Initialize the weight vector with a default value and the specified size
-        // NOTE: T::default could be used for tenosr::default
-        let weight = vec![Dtype::default(); in_features * out_features];
+        // weight is stored (out_features, in_features) like PyTorch, so checkpoints map 1:1
+        let weight = Tensor::zeros([out_features, in_features]);
 
         // Initialize the bias vector if needed
         let bias = if bias {
-            Some(vec![Dtype::default(); out_features])
+            Some(Tensor::zeros([out_features]))
         } else {
             None
         };
@@ -45,21 +51,95 @@ where
         }
     }
 
+    pub fn in_features(&self) -> usize {
+        self.in_features
+    }
+
+    pub fn out_features(&self) -> usize {
+        self.out_features
+    }
+
+    /// Runs the layer over a `(*, in_features)` input.
     ///
-    pub fn forward(&self, x: Vec<Dtype>) -> Vec<Dtype>
-    where
-        Dtype: Clone,
-    {
-        assert!(x.len() % self.in_features == 0, "");
+    /// # Returns
+    /// - `Ok(Tensor<Dtype>)`: The `(*, out_features)` output, leading dimensions preserved.
+    /// - `Err(TensorError::InvalidShape)`: If the last dimension of `x` is not `in_features`.
+    pub fn forward(&self, x: &Tensor<Dtype>) -> Result<Tensor<Dtype>, TensorError> {
+        if x.shape.last() != Some(&self.in_features) {
+            let mut expected = x.shape.clone();
+            match expected.last_mut() {
+                Some(last) => *last = self.in_features,
+                None => expected.push(self.in_features),
+            }
+            return Err(TensorError::InvalidShape {
+                expected,
+                found: x.shape.clone(),
+            });
+        }
 
         // apply W (out_features, in_features)
         // apply x (*, in_features)
         // bias    (1, out_features)
         // output  (*, out_features)
         // xW^T + b
-
-        
+        let mut y = x.matmul_transposed_(&self.weight)?;
+        if let Some(bias) = &self.bias {
+            y += bias;
+        }
+        Ok(y)
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::Linear;
+    use crate::core::tensor::error::TensorError;
+    use crate::core::Tensor;
+
+    fn layer() -> Linear<f32> {
+        // W = [[1, 2, 3], [0, -1, 1]], b = [0.5, -0.5]
+        let mut linear = Linear::<f32>::new(3, 2, true);
+        linear.weight = Tensor::new([2, 3], vec![1.0, 2.0, 3.0, 0.0, -1.0, 1.0]).unwrap();
+        linear.bias = Some(Tensor::new([2], vec![0.5, -0.5]).unwrap());
+        linear
+    }
 
+    #[test]
+    fn test_linear_new() {
+        let linear = Linear::<f32>::new(4, 3, false);
+        assert_eq!(linear.weight.shape, vec![3, 4]);
+        assert!(linear.bias.is_none());
+        assert_eq!((linear.in_features(), linear.out_features()), (4, 3));
+    }
+
+    #[test]
+    fn test_linear_forward() {
+        let x = Tensor::new([2, 3], vec![1.0, 1.0, 1.0, 1.0, 0.0, -1.0]).unwrap();
+        let y = layer().forward(&x).unwrap();
+        assert_eq!(y.shape, vec![2, 2]);
+        assert_eq!(y.storage, vec![6.5, -0.5, -1.5, -1.5]);
+    }
+
+    #[test]
+    fn test_linear_leading_dims() {
+        let x = Tensor::new([2, 1, 2, 3], (0..12).map(|v| v as f32).collect()).unwrap();
+        let y = layer().forward(&x).unwrap();
+        assert_eq!(y.shape, vec![2, 1, 2, 2]);
+        assert_eq!(y.storage[4..], [44.5, 0.5, 62.5, 0.5]);
+
+        let v = Tensor::new([3], vec![1.0, 0.0, 0.0]).unwrap();
+        assert_eq!(layer().forward(&v).unwrap().storage, vec![1.5, -0.5]);
+    }
+
+    #[test]
+    fn test_linear_shape_error() {
+        let x = Tensor::new([4, 2], vec![0.0; 8]).unwrap();
+        assert_eq!(
+            layer().forward(&x).unwrap_err(),
+            TensorError::InvalidShape {
+                expected: vec![4, 3],
+                found: vec![4, 2]
+            }
+        );
+    }
+}
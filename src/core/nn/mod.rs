@@ -1,6 +1,29 @@
 pub (crate)mod activation;
 mod linear;
 mod embedding;
+mod layer_norm;
+mod attention;
+mod rotary;
+mod kv_cache;
+mod decoder;
 
 pub (crate)use linear::Linear;
-pub (crate)use embedding::Embedding;
\ No newline at end of file
+pub (crate)use embedding::Embedding;
+pub (crate)use layer_norm::LayerNorm;
+pub (crate)use attention::MultiHeadAttention;
+pub (crate)use rotary::RotaryEmbedding;
+pub (crate)use kv_cache::KvCache;
+pub (crate)use decoder::PhiDecoderLayer;
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::core::tensor::Float;
+
+    /// Asserts that `a` and `b` have the same length and differ by less than `tolerance`
+    /// element-wise.
+    pub(crate) fn assert_close<Dtype: Float>(a: &[Dtype], b: &[Dtype], tolerance: f64) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!((x.to_f64() - y.to_f64()).abs() < tolerance, "{:?} != {:?}", a, b);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use crate::core::tensor::error::TensorError;
+use crate::core::tensor::{Float, Tensor};
+
+pub struct RotaryEmbedding<Dtype> {
+    head_dim: usize,
+    rotary_dim: usize,
+    max_position_embeddings: usize,
+    // (max_position_embeddings, rotary_dim / 2)
+    cos: Vec<Dtype>,
+    sin: Vec<Dtype>,
+}
+
+/// Rotary position embeddings (RoPE) applied to the leading `rotary_dim` channels of
+/// every head, leaving the rest untouched. Phi-2 rotates 32 of its 80 channels
+/// (`partial_rotary_factor = 0.4`).
+///
+/// Channels are paired "rotate half" style, as in GPT-NeoX and the Hugging Face phi
+/// implementation: channel `i` is rotated together with channel `i + rotary_dim / 2`.
+impl<Dtype> RotaryEmbedding<Dtype>
+where
+    Dtype: Float,
+{
+    /// Precomputes the `sin`/`cos` tables for positions `0..max_position_embeddings`.
+    ///
+    /// # Parameters
+    /// - `head_dim`: Channels per attention head.
+    /// - `partial_rotary_factor`: Fraction of `head_dim` that is rotated.
+    /// - `rope_theta`: Base of the geometric frequency progression.
+    /// - `max_position_embeddings`: Number of positions to cache.
+    pub fn new(
+        head_dim: usize,
+        partial_rotary_factor: f64,
+        rope_theta: f64,
+        max_position_embeddings: usize,
+    ) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&partial_rotary_factor),
+            "ValueError: partial_rotary_factor={}, must be within [0, 1]",
+            partial_rotary_factor
+        );
+        // matches `int(partial_rotary_factor * head_dim)` in the reference implementation
+        let rotary_dim = (partial_rotary_factor * head_dim as f64) as usize;
+        assert!(
+            rotary_dim.is_multiple_of(2),
+            "ValueError: rotary_dim={} must be even",
+            rotary_dim
+        );
+
+        let half = rotary_dim / 2;
+        let inv_freq: Vec<f64> = (0..half)
+            .map(|i| 1.0 / rope_theta.powf((2 * i) as f64 / rotary_dim as f64))
+            .collect();
+
+        let mut cos = Vec::with_capacity(max_position_embeddings * half);
+        let mut sin = Vec::with_capacity(max_position_embeddings * half);
+        for position in 0..max_position_embeddings {
+            for &f in &inv_freq {
+                let angle = position as f64 * f;
+                cos.push(Dtype::from_f64(angle.cos()));
+                sin.push(Dtype::from_f64(angle.sin()));
+            }
+        }
+
+        RotaryEmbedding {
+            head_dim,
+            rotary_dim,
+            max_position_embeddings,
+            cos,
+            sin,
+        }
+    }
+
+    pub fn rotary_dim(&self) -> usize {
+        self.rotary_dim
+    }
+
+    pub fn max_position_embeddings(&self) -> usize {
+        self.max_position_embeddings
+    }
+
+    /// Rotates `x` of shape `(batch, heads, seq_len, head_dim)`, whose sequence starts
+    /// at absolute position `offset`.
+    ///
+    /// A full prompt uses `offset = 0`; during incremental decoding `x` holds only the
+    /// new token(s) and `offset` is the number of positions already in the cache.
+    ///
+    /// # Returns
+    /// - `Ok(Tensor<Dtype>)`: The rotated tensor, same shape as `x`.
+    /// - `Err(TensorError::InvalidShape)`: If the last dimension is not `head_dim`.
+    /// - `Err(TensorError::OutOfBounds)`: If a position reaches `max_position_embeddings`.
+    pub fn forward(&self, x: &Tensor<Dtype>, offset: usize) -> Result<Tensor<Dtype>, TensorError> {
+        if x.rank() != 4 || x.shape[3] != self.head_dim {
+            return Err(TensorError::InvalidShape {
+                expected: vec![
+                    x.dim(0).unwrap_or(1),
+                    x.dim(1).unwrap_or(1),
+                    x.dim(2).unwrap_or(1),
+                    self.head_dim,
+                ],
+                found: x.shape.clone(),
+            });
+        }
+        let seq_len = x.shape[2];
+        if offset + seq_len > self.max_position_embeddings {
+            return Err(TensorError::OutOfBounds {
+                index: offset + seq_len - 1,
+            });
+        }
+
+        let half = self.rotary_dim / 2;
+        let mut y = x.clone();
+        if half == 0 {
+            return Ok(y);
+        }
+        for (row, channels) in y.storage.chunks_exact_mut(self.head_dim).enumerate() {
+            let position = offset + row % seq_len;
+            let cos = &self.cos[position * half..(position + 1) * half];
+            let sin = &self.sin[position * half..(position + 1) * half];
+            let (x1, rest) = channels.split_at_mut(half);
+            let x2 = &mut rest[..half];
+            for i in 0..half {
+                let (a, b) = (x1[i], x2[i]);
+                x1[i] = a * cos[i] - b * sin[i];
+                x2[i] = b * cos[i] + a * sin[i];
+            }
+        }
+        Ok(y)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RotaryEmbedding;
+    use crate::core::nn::test::assert_close;
+    use crate::core::tensor::error::TensorError;
+    use crate::core::tensor::Tensor;
+
+    #[test]
+    fn test_partial_rotation() {
+        // head_dim 8, factor 0.25 -> rotary_dim 2: only channels 0 and 1 rotate
+        let rope = RotaryEmbedding::<f64>::new(8, 0.25, 10_000.0, 8);
+        assert_eq!(rope.rotary_dim(), 2);
+        let mut data = vec![7.0; 16];
+        data[..2].copy_from_slice(&[1.0, 0.0]);
+        data[8..10].copy_from_slice(&[1.0, 0.0]);
+        let x = Tensor::new([1, 1, 2, 8], data).unwrap();
+        let y = rope.forward(&x, 0).unwrap();
+        // position 0 is the identity, position 1 rotates by 1 radian (inv_freq[0] = 1)
+        assert_close(&y.storage[..8], &x.storage[..8], 1e-9);
+        assert_close(&y.storage[8..10], &[1f64.cos(), 1f64.sin()], 1e-9);
+        assert_close(&y.storage[10..], &[7.0; 6], 1e-9);
+    }
+
+    #[test]
+    fn test_frequencies() {
+        // rotary_dim 4: pairs (0, 2) at theta^0 and (1, 3) at theta^-1/2
+        let rope = RotaryEmbedding::<f64>::new(4, 1.0, 100.0, 4);
+        let x = Tensor::new([1, 1, 1, 4], vec![1.0, 1.0, 0.0, 0.0]).unwrap();
+        let y = rope.forward(&x, 3).unwrap();
+        let (a0, a1): (f64, f64) = (3.0, 3.0 / 10.0);
+        assert_close(&y.storage, &[a0.cos(), a1.cos(), a0.sin(), a1.sin()], 1e-9);
+    }
+
+    #[test]
+    fn test_offset_matches_full_sequence() {
+        let rope = RotaryEmbedding::<f64>::new(8, 0.5, 10_000.0, 16);
+        let x = Tensor::new([1, 2, 5, 8], (0..80).map(|v| (v as f64).sin()).collect()).unwrap();
+        let full = rope.forward(&x, 0).unwrap();
+        // the last position alone, decoded incrementally at offset 4
+        for h in 0..2 {
+            let token = x
+                .narrow(1, h, 1)
+                .unwrap()
+                .narrow(2, 4, 1)
+                .unwrap()
+                .contiguous();
+            let step = rope.forward(&token, 4).unwrap();
+            let start = (h * 5 + 4) * 8;
+            assert_close(&step.storage, &full.storage[start..start + 8], 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_position() {
+        let rope = RotaryEmbedding::<f32>::new(4, 1.0, 10_000.0, 4);
+        let x = Tensor::new([1, 1, 2, 4], vec![0.0; 8]).unwrap();
+        assert!(rope.forward(&x, 2).is_ok());
+        assert_eq!(
+            rope.forward(&x, 3).unwrap_err(),
+            TensorError::OutOfBounds { index: 4 }
+        );
+    }
+}
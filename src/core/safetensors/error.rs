@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use crate::core::tensor::error::TensorError;
+
+#[derive(Debug)]
+pub enum SafeTensorError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    InvalidHeader {
+        reason: String,
+    },
+    UnsupportedDtype {
+        dtype: String,
+    },
+    MissingTensor {
+        name: String,
+    },
+    Tensor {
+        name: String,
+        source: TensorError,
+    },
+}
+
+impl std::fmt::Display for SafeTensorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SafeTensorError::Io { path, source } => {
+                write!(f, "Failed to read '{}': {}", path.display(), source)
+            }
+            SafeTensorError::Write { path, source } => {
+                write!(f, "Failed to write '{}': {}", path.display(), source)
+            }
+            SafeTensorError::InvalidHeader { reason } => {
+                write!(f, "Invalid safetensors header: {}", reason)
+            }
+            SafeTensorError::UnsupportedDtype { dtype } => {
+                write!(f, "Unsupported safetensors dtype: {}", dtype)
+            }
+            SafeTensorError::MissingTensor { name } => {
+                write!(f, "Tensor '{}' not found in checkpoint", name)
+            }
+            SafeTensorError::Tensor { name, source } => {
+                write!(f, "Tensor '{}': {}", name, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SafeTensorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SafeTensorError::Io { source, .. } => Some(source),
+            SafeTensorError::Write { source, .. } => Some(source),
+            SafeTensorError::Tensor { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
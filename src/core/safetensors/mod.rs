@@ -0,0 +1,679 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::mem::size_of;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use half::{bf16, f16};
+use serde::Deserialize;
+
+use crate::core::tensor::storage::Mmap;
+use crate::core::tensor::{Float, Storage, Tensor, TensorView};
+
+pub(crate) mod error;
+
+use error::SafeTensorError;
+
+/// Element types a safetensors file may store that we know how to convert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SafeDtype {
+    F16,
+    BF16,
+    F32,
+    F64,
+}
+
+impl SafeDtype {
+    fn parse(dtype: &str) -> Result<Self, SafeTensorError> {
+        match dtype {
+            "F16" => Ok(SafeDtype::F16),
+            "BF16" => Ok(SafeDtype::BF16),
+            "F32" => Ok(SafeDtype::F32),
+            "F64" => Ok(SafeDtype::F64),
+            _ => Err(SafeTensorError::UnsupportedDtype {
+                dtype: dtype.to_string(),
+            }),
+        }
+    }
+
+    /// Name of the dtype in the header.
+    pub fn name(self) -> &'static str {
+        match self {
+            SafeDtype::F16 => "F16",
+            SafeDtype::BF16 => "BF16",
+            SafeDtype::F32 => "F32",
+            SafeDtype::F64 => "F64",
+        }
+    }
+
+    /// Bytes per element.
+    pub fn size(self) -> usize {
+        match self {
+            SafeDtype::F16 | SafeDtype::BF16 => 2,
+            SafeDtype::F32 => 4,
+            SafeDtype::F64 => 8,
+        }
+    }
+
+    /// Whether the stored bytes already are `Dtype` values, so they can be used in place.
+    fn is_native<Dtype: Float>(self) -> bool {
+        let native = match self {
+            SafeDtype::F32 | SafeDtype::F64 => self.size() == size_of::<Dtype>(),
+            SafeDtype::F16 | SafeDtype::BF16 => false,
+        };
+        native && cfg!(target_endian = "little")
+    }
+
+    /// Decodes little-endian `bytes` into `Dtype`, widening or rounding as needed.
+    fn decode<Dtype: Float>(self, bytes: &[u8]) -> Vec<Dtype> {
+        match self {
+            SafeDtype::F16 => bytes
+                .chunks_exact(2)
+                .map(|b| Dtype::from_f64(f16::from_le_bytes([b[0], b[1]]).to_f64()))
+                .collect(),
+            SafeDtype::BF16 => bytes
+                .chunks_exact(2)
+                .map(|b| Dtype::from_f64(bf16::from_le_bytes([b[0], b[1]]).to_f64()))
+                .collect(),
+            SafeDtype::F32 => bytes
+                .chunks_exact(4)
+                .map(|b| Dtype::from_f64(f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64))
+                .collect(),
+            SafeDtype::F64 => bytes
+                .chunks_exact(8)
+                .map(|b| Dtype::from_f64(f64::from_le_bytes(b.try_into().unwrap())))
+                .collect(),
+        }
+    }
+
+    /// Encodes `value` as little-endian bytes, rounding to nearest when narrowing.
+    fn encode<Dtype: Float>(self, value: Dtype, out: &mut impl Write) -> std::io::Result<()> {
+        let value = value.to_f64();
+        match self {
+            SafeDtype::F16 => out.write_all(&f16::from_f64(value).to_le_bytes()),
+            SafeDtype::BF16 => out.write_all(&bf16::from_f64(value).to_le_bytes()),
+            SafeDtype::F32 => out.write_all(&(value as f32).to_le_bytes()),
+            SafeDtype::F64 => out.write_all(&value.to_le_bytes()),
+        }
+    }
+}
+
+/// Header entry describing one tensor of a safetensors file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorInfo {
+    pub dtype: SafeDtype,
+    pub shape: Vec<usize>,
+    /// Byte range of the tensor, relative to the start of the data section.
+    pub data_offsets: (usize, usize),
+}
+
+#[derive(Deserialize)]
+struct RawTensorInfo {
+    dtype: String,
+    shape: Vec<usize>,
+    data_offsets: (usize, usize),
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, SafeTensorError> {
+    fs::read(path).map_err(|source| SafeTensorError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+enum Buffer {
+    Owned(Vec<u8>),
+    Mapped(Arc<Mmap>),
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Buffer::Owned(bytes) => bytes,
+            Buffer::Mapped(map) => map,
+        }
+    }
+}
+
+fn invalid_header(reason: impl Into<String>) -> SafeTensorError {
+    SafeTensorError::InvalidHeader {
+        reason: reason.into(),
+    }
+}
+
+/// A single safetensors file: an 8-byte little-endian header length, a JSON header
+/// mapping tensor names to dtype, shape and byte range, then the raw tensor data.
+///
+/// On unix, files opened with [`SafeTensors::read`] are memory-mapped rather than read
+/// into memory, and tensors already stored in the requested `Dtype` borrow their elements
+/// from the mapping instead of copying them.
+/// On other platforms the whole file is read into memory.
+///
+/// Only F32 and F64 data can be borrowed: tensors have no half-precision storage, so F16
+/// and BF16 data, such as the published phi-2 weights, is widened into a new `Vec`. A
+/// half-precision checkpoint loaded as `f32` therefore takes about twice its file size in
+/// memory; convert it to F32 with [`save`] once to load it in place.
+pub struct SafeTensors {
+    tensors: BTreeMap<String, TensorInfo>,
+    metadata: BTreeMap<String, String>,
+    buffer: Buffer,
+    data_start: usize,
+}
+
+impl SafeTensors {
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, SafeTensorError> {
+        let path = path.as_ref();
+        #[cfg(unix)]
+        let buffer = Buffer::Mapped(Arc::new(Mmap::open(path).map_err(|source| {
+            SafeTensorError::Io {
+                path: path.to_path_buf(),
+                source,
+            }
+        })?));
+        #[cfg(not(unix))]
+        let buffer = Buffer::Owned(read_file(path)?);
+        SafeTensors::parse(buffer)
+    }
+
+    /// Parses and validates the header of an in-memory file.
+    pub fn from_bytes(buffer: Vec<u8>) -> Result<Self, SafeTensorError> {
+        SafeTensors::parse(Buffer::Owned(buffer))
+    }
+
+    fn parse(buffer: Buffer) -> Result<Self, SafeTensorError> {
+        let header_len = buffer
+            .get(..8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| invalid_header("file is shorter than the 8-byte header length"))?;
+        let data_start = 8usize
+            .checked_add(header_len)
+            .filter(|&end| end <= buffer.len())
+            .ok_or_else(|| invalid_header(format!("header length {} exceeds file", header_len)))?;
+
+        let mut header: BTreeMap<String, serde_json::Value> =
+            serde_json::from_slice(&buffer[8..data_start])
+                .map_err(|e| invalid_header(e.to_string()))?;
+        let metadata = match header.remove("__metadata__") {
+            Some(value) => {
+                serde_json::from_value(value).map_err(|e| invalid_header(e.to_string()))?
+            }
+            None => BTreeMap::new(),
+        };
+
+        let data_len = buffer.len() - data_start;
+        let mut tensors = BTreeMap::new();
+        for (name, value) in header {
+            let raw: RawTensorInfo = serde_json::from_value(value)
+                .map_err(|e| invalid_header(format!("tensor '{}': {}", name, e)))?;
+            let dtype = SafeDtype::parse(&raw.dtype)?;
+            let (begin, end) = raw.data_offsets;
+            let expected = raw
+                .shape
+                .iter()
+                .try_fold(dtype.size(), |bytes, &dim| bytes.checked_mul(dim))
+                .ok_or_else(|| {
+                    invalid_header(format!(
+                        "tensor '{}' has oversized shape {:?}",
+                        name, raw.shape
+                    ))
+                })?;
+            if begin > end || end > data_len || end - begin != expected {
+                return Err(invalid_header(format!(
+                    "tensor '{}' has data_offsets {:?}, expected {} bytes within {}",
+                    name, raw.data_offsets, expected, data_len
+                )));
+            }
+            tensors.insert(
+                name,
+                TensorInfo {
+                    dtype,
+                    shape: raw.shape,
+                    data_offsets: raw.data_offsets,
+                },
+            );
+        }
+
+        Ok(SafeTensors {
+            tensors,
+            metadata,
+            buffer,
+            data_start,
+        })
+    }
+
+    /// Tensor names in sorted order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.tensors.keys().map(String::as_str)
+    }
+
+    pub fn info(&self, name: &str) -> Option<&TensorInfo> {
+        self.tensors.get(name)
+    }
+
+    /// The free-form `__metadata__` string map of the header.
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
+
+    /// Decodes the tensor `name` into a `Dtype` tensor, whatever its stored dtype.
+    ///
+    /// When the file is memory-mapped and stores `Dtype` itself, the tensor borrows the
+    /// mapping (see [`Storage`]); otherwise, including for any F16 or BF16 data, its
+    /// elements are converted into a new `Vec`.
+    pub fn tensor<Dtype: Float>(&self, name: &str) -> Result<Tensor<Dtype>, SafeTensorError> {
+        let info = self
+            .tensors
+            .get(name)
+            .ok_or_else(|| SafeTensorError::MissingTensor {
+                name: name.to_string(),
+            })?;
+        let (begin, end) = info.data_offsets;
+        let (begin, end) = (self.data_start + begin, self.data_start + end);
+        let mapped = match &self.buffer {
+            Buffer::Mapped(map) if info.dtype.is_native::<Dtype>() => {
+                let len = (end - begin) / info.dtype.size();
+                Storage::mapped(Arc::clone(map), begin, len)
+            }
+            _ => None,
+        };
+        // misaligned data falls back to a copy
+        let storage =
+            mapped.unwrap_or_else(|| Storage::Owned(info.dtype.decode(&self.buffer[begin..end])));
+        Tensor::from_storage(info.shape.clone(), storage).map_err(|source| {
+            SafeTensorError::Tensor {
+                name: name.to_string(),
+                source,
+            }
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct ShardIndex {
+    weight_map: BTreeMap<String, String>,
+}
+
+/// The weights of a model, stored either in a single safetensors file or sharded
+/// across several files listed by a `model.safetensors.index.json`.
+pub struct Checkpoint {
+    shards: Vec<SafeTensors>,
+    // tensor name -> index into `shards`
+    weight_map: HashMap<String, usize>,
+}
+
+impl Checkpoint {
+    /// Opens `path`, which may be a `.safetensors` file, a sharded checkpoint's
+    /// `*.index.json`, or a model directory holding either `model.safetensors.index.json`
+    /// or `model.safetensors`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, SafeTensorError> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            let index = path.join("model.safetensors.index.json");
+            if index.is_file() {
+                return Checkpoint::from_index(index);
+            }
+            return Checkpoint::from_file(path.join("model.safetensors"));
+        }
+        if path.extension().is_some_and(|ext| ext == "json") {
+            return Checkpoint::from_index(path);
+        }
+        Checkpoint::from_file(path)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SafeTensorError> {
+        let shard = SafeTensors::read(path)?;
+        let weight_map = shard.names().map(|name| (name.to_string(), 0)).collect();
+        Ok(Checkpoint {
+            shards: vec![shard],
+            weight_map,
+        })
+    }
+
+    /// Loads every shard named in the `weight_map` of an index file; shard paths are
+    /// relative to the index.
+    pub fn from_index(path: impl AsRef<Path>) -> Result<Self, SafeTensorError> {
+        let path = path.as_ref();
+        let index: ShardIndex = serde_json::from_slice(&read_file(path)?)
+            .map_err(|e| invalid_header(format!("{}: {}", path.display(), e)))?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        let mut files: Vec<&String> = index.weight_map.values().collect();
+        files.sort();
+        files.dedup();
+        let mut shards = Vec::with_capacity(files.len());
+        let mut shard_of_file = HashMap::new();
+        for file in files {
+            let shard_path: PathBuf = dir.join(file);
+            shard_of_file.insert(file, shards.len());
+            shards.push(SafeTensors::read(shard_path)?);
+        }
+
+        let mut weight_map = HashMap::with_capacity(index.weight_map.len());
+        for (name, file) in &index.weight_map {
+            let shard = shard_of_file[file];
+            if shards[shard].info(name).is_none() {
+                return Err(SafeTensorError::MissingTensor { name: name.clone() });
+            }
+            weight_map.insert(name.clone(), shard);
+        }
+        Ok(Checkpoint { shards, weight_map })
+    }
+
+    pub fn len(&self) -> usize {
+        self.weight_map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.weight_map.is_empty()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.weight_map.contains_key(name)
+    }
+
+    pub fn tensor<Dtype: Float>(&self, name: &str) -> Result<Tensor<Dtype>, SafeTensorError> {
+        let shard = self
+            .weight_map
+            .get(name)
+            .ok_or_else(|| SafeTensorError::MissingTensor {
+                name: name.to_string(),
+            })?;
+        self.shards[*shard].tensor(name)
+    }
+}
+
+/// Writes `tensors` to `path` as a safetensors file, every tensor stored as `dtype`.
+///
+/// The header records each tensor's dtype and shape next to the free-form `metadata`;
+/// the data section is streamed, so no second copy of the weights is built in memory.
+///
+/// The file is written next to `path` under a temporary name and then renamed over it,
+/// so `path` may be the very file `tensors` are memory-mapped from: the mapping keeps
+/// the old file alive instead of seeing it truncated.
+pub fn save<'a, Dtype: Float + 'a>(
+    path: impl AsRef<Path>,
+    tensors: &[(String, TensorView<'a, Dtype>)],
+    dtype: SafeDtype,
+    metadata: &BTreeMap<String, String>,
+) -> Result<(), SafeTensorError> {
+    let path = path.as_ref();
+    let mut header = serde_json::Map::new();
+    if !metadata.is_empty() {
+        header.insert("__metadata__".to_string(), serde_json::json!(metadata));
+    }
+    let mut offset = 0;
+    for (name, view) in tensors {
+        let end = offset + view.numel() * dtype.size();
+        let info = serde_json::json!({
+            "dtype": dtype.name(),
+            "shape": view.shape,
+            "data_offsets": [offset, end],
+        });
+        if name == "__metadata__" {
+            return Err(invalid_header(
+                "tensor name '__metadata__' is reserved for the header metadata",
+            ));
+        }
+        if header.insert(name.clone(), info).is_some() {
+            return Err(invalid_header(format!("duplicate tensor name '{}'", name)));
+        }
+        offset = end;
+    }
+    let mut header = serde_json::to_vec(&header).map_err(|e| invalid_header(e.to_string()))?;
+    // pad with spaces so the data section starts 8-byte aligned and can be mapped in place
+    header.resize(header.len().next_multiple_of(8), b' ');
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+    let write = || -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        writer.write_all(&(header.len() as u64).to_le_bytes())?;
+        writer.write_all(&header)?;
+        for (_, view) in tensors {
+            for &value in view.iter() {
+                dtype.encode(value, &mut writer)?;
+            }
+        }
+        writer.flush()
+    };
+    write()
+        .and_then(|_| fs::rename(&temp_path, path))
+        .map_err(|source| {
+            let _ = fs::remove_file(&temp_path);
+            SafeTensorError::Write {
+                path: path.to_path_buf(),
+                source,
+            }
+        })
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use half::{bf16, f16};
+
+    use super::error::SafeTensorError;
+    use super::{save, Checkpoint, SafeDtype, SafeTensors};
+    use crate::core::tensor::Tensor;
+
+    /// Builds a safetensors file from `(name, dtype, shape, little-endian bytes)` entries.
+    pub(crate) fn serialize(entries: &[(&str, &str, Vec<usize>, Vec<u8>)]) -> Vec<u8> {
+        let mut header = serde_json::Map::new();
+        header.insert("__metadata__".into(), serde_json::json!({"format": "pt"}));
+        let mut data = Vec::new();
+        for (name, dtype, shape, bytes) in entries {
+            header.insert(
+                name.to_string(),
+                serde_json::json!({
+                    "dtype": dtype,
+                    "shape": shape,
+                    "data_offsets": [data.len(), data.len() + bytes.len()],
+                }),
+            );
+            data.extend_from_slice(bytes);
+        }
+        let mut header = serde_json::to_vec(&header).unwrap();
+        // like the reference writer, pad the header so the data section is 8-byte aligned
+        header.resize(header.len().next_multiple_of(8), b' ');
+        let mut buffer = (header.len() as u64).to_le_bytes().to_vec();
+        buffer.extend(header);
+        buffer.extend(data);
+        buffer
+    }
+
+    pub(crate) fn f32_bytes(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("phi-2-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_read_and_convert() {
+        let half: Vec<u8> = [1.5f32, -2.0]
+            .iter()
+            .flat_map(|&v| f16::from_f32(v).to_le_bytes())
+            .collect();
+        let brain: Vec<u8> = [0.25f32, 3.0]
+            .iter()
+            .flat_map(|&v| bf16::from_f32(v).to_le_bytes())
+            .collect();
+        let file = SafeTensors::from_bytes(serialize(&[
+            ("a", "F16", vec![2], half),
+            ("b", "BF16", vec![1, 2], brain),
+            ("c", "F32", vec![2, 1], f32_bytes(&[0.1, 7.0])),
+        ]))
+        .unwrap();
+
+        assert_eq!(file.names().collect::<Vec<_>>(), vec!["a", "b", "c"]);
+        assert_eq!(file.metadata()["format"], "pt");
+        assert_eq!(file.info("b").unwrap().dtype, SafeDtype::BF16);
+        assert_eq!(file.tensor::<f32>("a").unwrap().storage, vec![1.5, -2.0]);
+        let b = file.tensor::<f64>("b").unwrap();
+        assert_eq!(
+            (b.shape, b.storage.into_vec()),
+            (vec![1, 2], vec![0.25, 3.0])
+        );
+        assert_eq!(file.tensor::<f32>("c").unwrap().storage, vec![0.1, 7.0]);
+        assert!(matches!(
+            file.tensor::<f32>("d"),
+            Err(SafeTensorError::MissingTensor { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert!(matches!(
+            SafeTensors::from_bytes(vec![1, 2, 3]),
+            Err(SafeTensorError::InvalidHeader { .. })
+        ));
+        assert!(matches!(
+            SafeTensors::from_bytes(serialize(&[("x", "I8", vec![1], vec![0])])),
+            Err(SafeTensorError::UnsupportedDtype { .. })
+        ));
+        // shape says two floats but only one is stored
+        assert!(matches!(
+            SafeTensors::from_bytes(serialize(&[("x", "F32", vec![2], f32_bytes(&[1.0]))])),
+            Err(SafeTensorError::InvalidHeader { .. })
+        ));
+        // the byte length overflows, and would wrap to the 0 bytes stored
+        for shape in [vec![1 << 62], vec![1 << 32, 1 << 32]] {
+            assert!(matches!(
+                SafeTensors::from_bytes(serialize(&[("x", "F32", shape, vec![])])),
+                Err(SafeTensorError::InvalidHeader { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_sharded_checkpoint() {
+        let dir = temp_dir("sharded");
+        fs::write(
+            dir.join("model-00001-of-00002.safetensors"),
+            serialize(&[("x", "F32", vec![1], f32_bytes(&[1.0]))]),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("model-00002-of-00002.safetensors"),
+            serialize(&[("y", "F32", vec![2], f32_bytes(&[2.0, 3.0]))]),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("model.safetensors.index.json"),
+            r#"{"metadata": {"total_size": 12}, "weight_map": {
+                "x": "model-00001-of-00002.safetensors",
+                "y": "model-00002-of-00002.safetensors"}}"#,
+        )
+        .unwrap();
+
+        let checkpoint = Checkpoint::open(&dir).unwrap();
+        assert_eq!(checkpoint.len(), 2);
+        // stored as F32, so f32 tensors are used in place and f64 ones converted
+        assert_eq!(
+            checkpoint.tensor::<f32>("y").unwrap().storage.is_mapped(),
+            cfg!(unix)
+        );
+        assert!(!checkpoint.tensor::<f64>("y").unwrap().storage.is_mapped());
+        assert_eq!(checkpoint.tensor::<f32>("x").unwrap().storage, vec![1.0]);
+        assert_eq!(
+            checkpoint.tensor::<f32>("y").unwrap().storage,
+            vec![2.0, 3.0]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_half_precision_is_copied() {
+        let dir = temp_dir("half");
+        let path = dir.join("model.safetensors");
+        let half: Vec<u8> = [1.0f32, -2.5, 0.125]
+            .iter()
+            .flat_map(|&v| f16::from_f32(v).to_le_bytes())
+            .collect();
+        fs::write(&path, serialize(&[("w", "F16", vec![3], half)])).unwrap();
+
+        let file = SafeTensors::read(&path).unwrap();
+        assert_eq!(file.info("w").unwrap().dtype, SafeDtype::F16);
+        // there is no f16 storage, so both widths decode into an owned copy
+        let w = file.tensor::<f32>("w").unwrap();
+        assert!(!w.storage.is_mapped());
+        assert_eq!(w.storage, vec![1.0, -2.5, 0.125]);
+        assert!(!file.tensor::<f64>("w").unwrap().storage.is_mapped());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let dir = temp_dir("save");
+        let a = Tensor::new([2, 3], vec![0.5f32, -1.25, 3.0, 1e-3, 7.5, -0.0]).unwrap();
+        let b = Tensor::new([4], vec![1.0f32, 2.0, 3.0, 4.0]).unwrap();
+        // a transposed view is written in logical order
+        let tensors = vec![
+            ("a".to_string(), a.transpose(0, 1).unwrap()),
+            ("b".to_string(), b.view()),
+        ];
+        let metadata = BTreeMap::from([("format".to_string(), "pt".to_string())]);
+
+        let path = dir.join("f32.safetensors");
+        save(&path, &tensors, SafeDtype::F32, &metadata).unwrap();
+        let file = SafeTensors::read(&path).unwrap();
+        assert_eq!(file.metadata(), &metadata);
+        assert_eq!(file.info("a").unwrap().shape, vec![3, 2]);
+        assert_eq!(
+            file.tensor::<f32>("a").unwrap(),
+            a.transpose(0, 1).unwrap().contiguous()
+        );
+        let loaded = file.tensor::<f32>("b").unwrap();
+        assert_eq!(loaded.storage.is_mapped(), cfg!(unix));
+        assert_eq!(loaded, b);
+
+        let path = dir.join("f16.safetensors");
+        save(&path, &tensors, SafeDtype::F16, &BTreeMap::new()).unwrap();
+        let file = SafeTensors::read(&path).unwrap();
+        assert_eq!(file.info("b").unwrap().dtype, SafeDtype::F16);
+        assert_eq!(file.tensor::<f32>("b").unwrap(), b);
+        let rounded = a
+            .transpose(0, 1)
+            .unwrap()
+            .contiguous()
+            .map(|v| f16::from_f32(v).to_f32());
+        assert_eq!(file.tensor::<f32>("a").unwrap(), rounded);
+
+        let duplicate = vec![("b".to_string(), b.view()), ("b".to_string(), b.view())];
+        assert!(matches!(
+            save(
+                dir.join("dup.safetensors"),
+                &duplicate,
+                SafeDtype::F32,
+                &metadata
+            ),
+            Err(SafeTensorError::InvalidHeader { .. })
+        ));
+        let reserved = vec![("__metadata__".to_string(), b.view())];
+        let error = save(
+            dir.join("reserved.safetensors"),
+            &reserved,
+            SafeDtype::F32,
+            &metadata,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("reserved"), "{}", error);
+
+        let missing = dir.join("missing").join("model.safetensors");
+        let error = save(&missing, &tensors, SafeDtype::F32, &metadata).unwrap_err();
+        assert!(matches!(error, SafeTensorError::Write { ref path, .. } if *path == missing));
+        assert!(error.to_string().starts_with("Failed to write"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,25 +1,29 @@
-#[path = "../device.rs"]
-mod device;
-
-use device::Device;
+use crate::core::device::Device;
 
 #[derive(Debug, PartialEq)]
 pub enum TensorError {
     InvalidShape {
-        expected: (usize, usize),
-        found: (usize, usize),
+        expected: Vec<usize>,
+        found: Vec<usize>,
     },
     OutOfBounds {
         index: usize,
     },
     ArithmeticMismatch {
         operation: String,
-        shape1: (usize, usize),
-        shape2: (usize, usize),
+        shape1: Vec<usize>,
+        shape2: Vec<usize>,
     },
     NotImplemented {
         feature: String,
     },
+    NonContiguous {
+        shape: Vec<usize>,
+        strides: Vec<usize>,
+    },
+    EmptyTensor {
+        operation: String,
+    },
     InvalidDevice {
         found: (Device, Device),
     },
@@ -52,6 +56,20 @@ impl std::fmt::Display for TensorError {
             TensorError::NotImplemented { feature } => {
                 write!(f, "Feature not implemented: {}", feature)
             }
+            TensorError::NonContiguous { shape, strides } => {
+                write!(
+                    f,
+                    "View with shape {:?} and strides {:?} is not contiguous, call contiguous() first",
+                    shape, strides
+                )
+            }
+            TensorError::EmptyTensor { operation } => {
+                write!(
+                    f,
+                    "Operation '{}' is undefined on an empty tensor",
+                    operation
+                )
+            }
             TensorError::InvalidDevice { found } => {
                 write!(f, "Expected all tensors to be on the same device, but found at least two devices, {} and {}!", found.0, found.1)
             }
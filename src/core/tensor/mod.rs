@@ -4,10 +4,12 @@
 // low level hardware
 // pub type Tensor<T> = Vec<T>;
 
-#[path = "../device.rs"]
-pub(crate) mod device;
-
 pub(crate) mod error;
+pub(crate) mod float;
+mod ops;
+pub(crate) mod reduce;
+pub(crate) mod storage;
+pub(crate) mod view;
 
 // rand == "0.8.5"
 use rand::distributions::{Distribution, Standard};
@@ -19,19 +21,66 @@ use std::marker::PhantomData;
 use std::ops::{Add, Div, Mul, Sub};
 
 // tensor sub module(s)
-use device::Device;
+use crate::core::device::Device;
 use error::TensorError;
+pub use float::Float;
+pub use storage::Storage;
+pub use view::TensorView;
 
-// simple tensor form with no batch
+// N-dimensional, row-major tensor
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Tensor<Dtype> {
-    pub shape: (usize, usize),
-    pub storage: Vec<Dtype>,
+    pub shape: Vec<usize>,
+    pub strides: Vec<usize>,
+    pub storage: Storage<Dtype>,
     _storage: PhantomData<Dtype>,
     #[cfg(feature = "retain_gradients")]
     pub gradients: Option<Vec<Dtype>>,
 }
 
+/// Row-major (C order) strides for `shape`, the last dimension being contiguous.
+///
+/// # Example
+/// ```
+/// assert_eq!(contiguous_strides(&[2, 3, 4]), vec![12, 4, 1]);
+/// ```
+pub fn contiguous_strides(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+    for i in (0..shape.len().saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1] * shape[i + 1];
+    }
+    strides
+}
+
+/// Shape that `a` and `b` broadcast to, or `None` if they are incompatible.
+///
+/// Dimensions are compared from the right; a missing or size-1 dimension stretches to
+/// match the other operand.
+///
+/// # Example
+/// ```
+/// assert_eq!(broadcast_shape(&[4, 1, 3], &[2, 1]), Some(vec![4, 2, 3]));
+/// assert_eq!(broadcast_shape(&[2, 3], &[4]), None);
+/// ```
+pub fn broadcast_shape(a: &[usize], b: &[usize]) -> Option<Vec<usize>> {
+    let rank = a.len().max(b.len());
+    let dim = |s: &[usize], i: usize| {
+        if i < rank - s.len() {
+            1
+        } else {
+            s[i - (rank - s.len())]
+        }
+    };
+    (0..rank)
+        .map(|i| match (dim(a, i), dim(b, i)) {
+            (x, y) if x == y => Some(x),
+            (1, y) => Some(y),
+            (x, 1) => Some(x),
+            _ => None,
+        })
+        .collect()
+}
+
 /// Implementation of the `IntoTensor` trait for vectors.
 ///
 /// This implementation allows a vector of data to be converted into a tensor,
@@ -52,8 +101,8 @@ pub trait IntoTensor<Dtype> {
     /// # use your_crate::{Tensor, IntoTensor, TensorError};
     /// let vec = vec![1, 2, 3, 4, 5, 6];
     /// let tensor = vec.to_tensor(2, 3).unwrap();
-    /// assert_eq!(tensor.shape(), (2, 3));
-    /// assert_eq!(tensor.storage(), &[1, 2, 3, 4, 5, 6]);
+    /// assert_eq!(tensor.shape, vec![2, 3]);
+    /// assert_eq!(tensor.storage, vec![1, 2, 3, 4, 5, 6]);
     /// ```
     ///
     /// Error case:
@@ -63,7 +112,7 @@ pub trait IntoTensor<Dtype> {
     /// let vec = vec![1, 2, 3, 4, 5];
     /// let result = vec.to_tensor(2, 3);
     /// assert!(result.is_err());
-    /// assert_eq!(result.unwrap_err(), TensorError::InvalidShape { expected: (2, 3), found: (5, 1) });
+    /// assert_eq!(result.unwrap_err(), TensorError::InvalidShape { expected: vec![2, 3], found: vec![5] });
     /// ```
     ///
     /// # Parameters
@@ -84,21 +133,192 @@ where
     Dtype: Debug,
 {
     fn to_tensor(self, row: usize, col: usize) -> Result<Tensor<Dtype>, TensorError> {
-        if self.len() != row * col {
+        Tensor::from_shape_vec([row, col], self)
+    }
+}
+
+impl<Dtype> Tensor<Dtype> {
+    /// Wraps `storage` as a contiguous tensor of the given shape, checking that the
+    /// element count matches. Unlike [`Tensor::new`] this places no arithmetic bounds on
+    /// `Dtype`.
+    pub fn from_shape_vec(
+        shape: impl Into<Vec<usize>>,
+        storage: Vec<Dtype>,
+    ) -> Result<Self, TensorError> {
+        Tensor::from_storage(shape, Storage::Owned(storage))
+    }
+
+    /// Like [`Tensor::from_shape_vec`], for storage that may be memory-mapped.
+    pub fn from_storage(
+        shape: impl Into<Vec<usize>>,
+        storage: Storage<Dtype>,
+    ) -> Result<Self, TensorError> {
+        let shape = shape.into();
+        if storage.len() != shape.iter().product::<usize>() {
             return Err(TensorError::InvalidShape {
-                expected: (row, col),
-                found: (self.len(), 1),
+                expected: shape,
+                found: vec![storage.len()],
             });
         }
 
         Ok(Tensor {
-            shape: (row, col),
-            storage: self,
+            strides: contiguous_strides(&shape),
+            shape,
+            storage,
             _storage: PhantomData,
             #[cfg(feature = "retain_gradients")]
             gradients: None,
         })
     }
+
+    /// Number of dimensions.
+    #[inline]
+    pub fn rank(&self) -> usize {
+        self.shape.len()
+    }
+
+    /// Total number of elements, the product of all dimensions.
+    #[inline]
+    pub fn numel(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    /// Size of dimension `dim`, or `TensorError::OutOfBounds` for a dimension past the rank.
+    pub fn dim(&self, dim: usize) -> Result<usize, TensorError> {
+        self.shape
+            .get(dim)
+            .copied()
+            .ok_or(TensorError::OutOfBounds { index: dim })
+    }
+
+    /// Whether the strides describe a dense row-major layout of `shape`.
+    pub fn is_contiguous(&self) -> bool {
+        self.strides == contiguous_strides(&self.shape)
+    }
+
+    /// Flat storage offset of a multi-dimensional index.
+    pub fn offset_of(&self, index: &[usize]) -> Result<usize, TensorError> {
+        if index.len() != self.rank() {
+            return Err(TensorError::InvalidShape {
+                expected: self.shape.clone(),
+                found: index.to_vec(),
+            });
+        }
+        index
+            .iter()
+            .zip(self.shape.iter().zip(&self.strides))
+            .try_fold(0, |offset, (&i, (&dim, &stride))| {
+                if i >= dim {
+                    return Err(TensorError::OutOfBounds { index: i });
+                }
+                Ok(offset + i * stride)
+            })
+    }
+
+    /// Element at a multi-dimensional index.
+    pub fn get(&self, index: &[usize]) -> Result<&Dtype, TensorError> {
+        let offset = self.offset_of(index)?;
+        Ok(&self.storage[offset])
+    }
+}
+
+impl<Dtype> Tensor<Dtype>
+where
+    Dtype: Copy,
+{
+    /// Applies `f` to every element, keeping the shape.
+    pub fn map<F>(&self, f: F) -> Tensor<Dtype>
+    where
+        F: Fn(Dtype) -> Dtype,
+    {
+        let storage = self.storage.iter().map(|&a| f(a)).collect();
+        Tensor::from_shape_vec(self.shape.clone(), storage).unwrap()
+    }
+
+    /// Combines `self` and `y` elementwise under NumPy broadcasting rules.
+    ///
+    /// Shapes are aligned from the trailing dimension; each pair of dimensions must be
+    /// equal or one of them must be 1. Otherwise `TensorError::ArithmeticMismatch` is
+    /// returned, naming `operation`.
+    pub fn zip_with<F>(
+        &self,
+        y: &Tensor<Dtype>,
+        operation: &str,
+        f: F,
+    ) -> Result<Tensor<Dtype>, TensorError>
+    where
+        F: Fn(Dtype, Dtype) -> Dtype,
+    {
+        if self.shape == y.shape {
+            let storage = self
+                .storage
+                .iter()
+                .zip(y.storage.iter())
+                .map(|(&a, &b)| f(a, b))
+                .collect::<Vec<Dtype>>();
+            return Tensor::from_shape_vec(self.shape.clone(), storage);
+        }
+
+        let shape = broadcast_shape(&self.shape, &y.shape).ok_or_else(|| {
+            TensorError::ArithmeticMismatch {
+                operation: operation.to_string(),
+                shape1: self.shape.clone(),
+                shape2: y.shape.clone(),
+            }
+        })?;
+        let a = self.view().broadcast_to(shape.clone())?;
+        let b = y.view().broadcast_to(shape.clone())?;
+        let storage = a
+            .iter()
+            .zip(b.iter())
+            .map(|(&a, &b)| f(a, b))
+            .collect::<Vec<Dtype>>();
+        Tensor::from_shape_vec(shape, storage)
+    }
+
+    /// Updates `self` in place with `f(self, y)`, broadcasting `y` to the shape of `self`.
+    ///
+    /// The existing storage is reused, so `y` may be stretched but `self` may not.
+    pub fn zip_assign<F>(
+        &mut self,
+        y: &Tensor<Dtype>,
+        operation: &str,
+        f: F,
+    ) -> Result<(), TensorError>
+    where
+        F: Fn(Dtype, Dtype) -> Dtype,
+    {
+        if self.shape == y.shape {
+            for (a, &b) in self.storage.iter_mut().zip(y.storage.iter()) {
+                *a = f(*a, b);
+            }
+            return Ok(());
+        }
+
+        let mismatch = || TensorError::ArithmeticMismatch {
+            operation: operation.to_string(),
+            shape1: self.shape.clone(),
+            shape2: y.shape.clone(),
+        };
+        let b = y
+            .view()
+            .broadcast_to(self.shape.clone())
+            .map_err(|_| mismatch())?;
+        for (a, &b) in self.storage.iter_mut().zip(b.iter()) {
+            *a = f(*a, b);
+        }
+        Ok(())
+    }
+
+    /// Applies `f` to every element in place.
+    pub fn map_inplace<F>(&mut self, f: F)
+    where
+        F: Fn(Dtype) -> Dtype,
+    {
+        for a in self.storage.iter_mut() {
+            *a = f(*a);
+        }
+    }
 }
 
 impl<Dtype> Tensor<Dtype>
@@ -111,56 +331,33 @@ where
         + Mul<Output = Dtype>
         + Div<Output = Dtype>,
 {
-    pub fn new(shape: (usize, usize), storage: Vec<Dtype>) -> Result<Self, TensorError> {
-        if storage.len() != shape.0 * shape.1 {
-            return Err(TensorError::InvalidShape {
-                expected: shape,
-                found: (storage.len(), 1),
-            });
-        }
+    pub fn new(shape: impl Into<Vec<usize>>, storage: Vec<Dtype>) -> Result<Self, TensorError> {
+        Tensor::from_shape_vec(shape, storage)
+    }
 
-        Ok(Tensor {
-            shape,
-            storage,
-            _storage: PhantomData,
-            #[cfg(feature = "retain_gradients")]
-            gradients: None,
-        })
+    pub fn ones(shape: impl Into<Vec<usize>>) -> Self {
+        Tensor::full(shape, Dtype::from(1))
     }
 
-    pub fn ones(shape: (usize, usize)) -> Self {
-        let storage = vec![Dtype::from(1); shape.0 * shape.1];
-        Tensor {
-            shape,
-            storage,
-            _storage: PhantomData,
-            #[cfg(feature = "retain_gradients")]
-            gradients: None,
-        }
+    pub fn zeros(shape: impl Into<Vec<usize>>) -> Self {
+        Tensor::full(shape, Dtype::from(0))
     }
 
-    pub fn zeros(shape: (usize, usize)) -> Self {
-        let storage = vec![Dtype::from(0); shape.0 * shape.1];
-        Tensor {
-            shape,
-            storage,
-            _storage: PhantomData,
-            #[cfg(feature = "retain_gradients")]
-            gradients: None,
-        }
+    /// Tensor of the given shape with every element set to `value`.
+    pub fn full(shape: impl Into<Vec<usize>>, value: Dtype) -> Self {
+        let shape = shape.into();
+        let storage = vec![value; shape.iter().product()];
+        Tensor::from_shape_vec(shape, storage).unwrap()
     }
 
-    pub fn rand_f32(shape: (usize, usize)) -> Tensor<f32> {
+    pub fn rand_f32(shape: impl Into<Vec<usize>>) -> Tensor<f32> {
+        let shape = shape.into();
         let mut rng = rand::thread_rng();
-        let storage: Vec<f32> = (0..shape.0 * shape.1).map(|_| rng.gen::<f32>()).collect();
+        let storage: Vec<f32> = (0..shape.iter().product())
+            .map(|_| rng.gen::<f32>())
+            .collect();
 
-        Tensor {
-            shape,
-            storage,
-            _storage: PhantomData,
-            #[cfg(feature = "retain_gradients")]
-            gradients: None,
-        }
+        Tensor::from_shape_vec(shape, storage).unwrap()
     }
 
     pub fn retain_grad(&self) -> bool {
@@ -170,125 +367,308 @@ where
     ///
     /// Example:
     /// ```
-    /// let shape : (usize, usize) = (1, 5);
+    /// let shape = [1, 5];
     /// let t = Tensor::<f64>::rand_f64(shape);
     /// ```
-    pub fn rand_f64(shape: (usize, usize)) -> Tensor<f64> {
+    pub fn rand_f64(shape: impl Into<Vec<usize>>) -> Tensor<f64> {
+        let shape = shape.into();
         let mut rng = rand::thread_rng();
-        let storage: Vec<f64> = (0..shape.0 * shape.1).map(|_| rng.gen::<f64>()).collect();
+        let storage: Vec<f64> = (0..shape.iter().product())
+            .map(|_| rng.gen::<f64>())
+            .collect();
 
-        Tensor {
-            shape,
-            storage,
-            _storage: PhantomData,
-            #[cfg(feature = "retain_gradients")]
-            gradients: None,
-        }
+        Tensor::from_shape_vec(shape, storage).unwrap()
     }
 
-    // Implement this
+    /// Elementwise product, broadcasting `self` and `y` against each other.
+    ///
+    /// # Example
+    /// ```
+    /// let x = Tensor::new([2, 2], vec![1, 2, 3, 4]).unwrap();
+    /// let scale = Tensor::new([2], vec![10, 100]).unwrap();
+    /// assert_eq!(x.mul_(scale).unwrap().storage, vec![10, 200, 30, 400]);
+    /// ```
     #[inline]
     pub fn mul_(&self, y: Tensor<Dtype>) -> Result<Tensor<Dtype>, TensorError> {
-        if self.shape != y.shape {
-            return Err(TensorError::InvalidShape {
-                expected: self.shape,
-                found: y.shape,
-            });
-        }
-
-        let storage = self
-            .storage
-            .iter()
-            .zip(y.storage.iter())
-            .map(|(a, b)| *a * *b)
-            .collect::<Vec<Dtype>>();
-        Ok(Tensor::<Dtype>::new(self.shape.clone(), storage).unwrap())
+        self.zip_with(&y, "mul", |a, b| a * b)
     }
 
+    /// Elementwise sum, broadcasting `self` and `y` against each other.
     #[inline]
     pub fn add_(&self, y: Tensor<Dtype>) -> Result<Tensor<Dtype>, TensorError> {
-        if self.shape != y.shape {
-            return Err(TensorError::InvalidShape {
-                expected: self.shape,
-                found: y.shape,
-            });
-        }
-
-        let storage = self
-            .storage
-            .iter()
-            .zip(y.storage.iter())
-            .map(|(a, b)| *a + *b)
-            .collect::<Vec<Dtype>>();
-        Ok(Tensor::<Dtype>::new(self.shape.clone(), storage).unwrap())
+        self.zip_with(&y, "add", |a, b| a + b)
     }
 
+    /// Elementwise difference, broadcasting `self` and `y` against each other.
     #[inline]
     pub fn sub_(&self, y: Tensor<Dtype>) -> Result<Tensor<Dtype>, TensorError> {
-        if self.shape != y.shape {
-            return Err(TensorError::InvalidShape {
-                expected: self.shape,
-                found: y.shape,
-            });
-        }
-
-        let storage = self
-            .storage
-            .iter()
-            .zip(y.storage.iter())
-            .map(|(a, b)| *a - *b)
-            .collect::<Vec<Dtype>>();
-        Ok(Tensor::<Dtype>::new(self.shape.clone(), storage).unwrap())
+        self.zip_with(&y, "sub", |a, b| a - b)
     }
 
+    /// Elementwise quotient, broadcasting `self` and `y` against each other.
     #[inline]
     pub fn div_(&self, y: Tensor<Dtype>) -> Result<Tensor<Dtype>, TensorError> {
-        if self.shape != y.shape {
-            return Err(TensorError::InvalidShape {
-                expected: self.shape,
-                found: y.shape,
+        self.zip_with(&y, "div", |a, b| a / b)
+    }
+
+    /// Adds `y` to every element.
+    #[inline]
+    pub fn add_scalar(&self, y: Dtype) -> Tensor<Dtype> {
+        self.map(|a| a + y)
+    }
+
+    /// Subtracts `y` from every element.
+    #[inline]
+    pub fn sub_scalar(&self, y: Dtype) -> Tensor<Dtype> {
+        self.map(|a| a - y)
+    }
+
+    /// Multiplies every element by `y`.
+    #[inline]
+    pub fn mul_scalar(&self, y: Dtype) -> Tensor<Dtype> {
+        self.map(|a| a * y)
+    }
+
+    /// Divides every element by `y`.
+    #[inline]
+    pub fn div_scalar(&self, y: Dtype) -> Tensor<Dtype> {
+        self.map(|a| a / y)
+    }
+
+    /// Matrix product over the last two dimensions, `(*, m, k) x (*, k, n) -> (*, m, n)`.
+    ///
+    /// The leading batch dimensions of both operands must match, except that a 2-D `y`
+    /// is shared by every matrix in the batch of `self` (the `x @ W` case of a linear layer).
+    /// `y` is a `&Tensor` or a [`TensorView`]; a view whose matrices are dense blocks of
+    /// storage, like a narrowed KV cache, is read in place without being copied.
+    ///
+    /// The kernel walks the operands in `MATMUL_BLOCK` sized tiles so the working set of
+    /// `self`, `y` and the output stays in cache, and the inner loop runs over contiguous
+    /// rows of `y` and the output. Within each output element the reduction still runs in
+    /// increasing `k`, so the result matches a naive triple loop exactly.
+    ///
+    /// # Example
+    /// ```
+    /// let a = Tensor::new([2, 3], vec![1, 2, 3, 4, 5, 6]).unwrap();
+    /// let b = Tensor::new([3, 1], vec![1, 0, 1]).unwrap();
+    /// assert_eq!(a.matmul_(&b).unwrap().storage, vec![4, 10]);
+    /// ```
+    ///
+    /// # Returns
+    /// - `Ok(Tensor<Dtype>)`: The `(*, m, n)` product.
+    /// - `Err(TensorError::ArithmeticMismatch)`: If either operand has fewer than two
+    ///   dimensions, or the inner or batch dimensions do not agree.
+    pub fn matmul_<'y>(
+        &self,
+        y: impl Into<TensorView<'y, Dtype>>,
+    ) -> Result<Tensor<Dtype>, TensorError>
+    where
+        Dtype: 'y,
+    {
+        let y = y.into();
+        let mismatch = || TensorError::ArithmeticMismatch {
+            operation: "matmul".to_string(),
+            shape1: self.shape.clone(),
+            shape2: y.shape.clone(),
+        };
+        if self.rank() < 2 || y.rank() < 2 {
+            return Err(mismatch());
+        }
+
+        let (batch, mk) = self.shape.split_at(self.rank() - 2);
+        let (y_batch, kn) = y.shape.split_at(y.rank() - 2);
+        let (m, k, n) = (mk[0], mk[1], kn[1]);
+        if k != kn[0] || !(y_batch.is_empty() || y_batch == batch) {
+            return Err(mismatch());
+        }
+
+        let mut shape = batch.to_vec();
+        shape.extend([m, n]);
+        let mut storage = vec![Dtype::from(0); shape.iter().product()];
+        if storage.is_empty() {
+            // `chunks_exact_mut` rejects a zero chunk size when `m` or `n` is 0
+            return Tensor::new(shape, storage);
+        }
+
+        with_matrices(&y, |matrices| {
+            for (bi, out) in storage.chunks_exact_mut(m * n).enumerate() {
+                let a = &self.storage[bi * m * k..(bi + 1) * m * k];
+                let b = matrices[if y_batch.is_empty() { 0 } else { bi }];
+                gemm_blocked(a, b, out, m, k, n);
+            }
+        });
+
+        Tensor::new(shape, storage)
+    }
+
+    /// Product with transposed matrices, `(*, m, k) x (*, n, k)^T -> (*, m, n)`.
+    ///
+    /// With a 2-D `y` every leading dimension of `self` is a row, `(*, k) x (n, k)^T ->
+    /// (*, n)`: this is the `xW^T` of a linear layer with PyTorch's `(out_features,
+    /// in_features)` weight layout. Otherwise the batch dimensions must match, as for
+    /// `q k^T` in attention. Both operands are read along contiguous rows, so `y` never
+    /// has to be transposed in memory, and like [`Tensor::matmul_`] a dense view is not
+    /// copied either.
+    ///
+    /// # Returns
+    /// - `Ok(Tensor<Dtype>)`: The product.
+    /// - `Err(TensorError::ArithmeticMismatch)`: If the `k` or batch dimensions differ.
+    pub fn matmul_transposed_<'y>(
+        &self,
+        y: impl Into<TensorView<'y, Dtype>>,
+    ) -> Result<Tensor<Dtype>, TensorError>
+    where
+        Dtype: 'y,
+    {
+        let y = y.into();
+        let k = self.shape.last().copied().unwrap_or(0);
+        let shared = y.rank() == 2;
+        let batched = y.rank() > 2
+            && self.rank() == y.rank()
+            && self.shape[..self.rank() - 2] == y.shape[..y.rank() - 2];
+        if self.rank() == 0 || !(shared || batched) || y.shape.last() != Some(&k) {
+            return Err(TensorError::ArithmeticMismatch {
+                operation: "matmul_transposed".to_string(),
+                shape1: self.shape.clone(),
+                shape2: y.shape.clone(),
             });
         }
 
-        let storage = self
-            .storage
-            .iter()
-            .zip(y.storage.iter())
-            .map(|(a, b)| *a / *b)
-            .collect::<Vec<Dtype>>();
-        Ok(Tensor::<Dtype>::new(self.shape.clone(), storage).unwrap())
+        let n = y.shape[y.rank() - 2];
+        // rows of `self` per matrix of `y`
+        let m: usize = if shared {
+            self.shape[..self.rank() - 1].iter().product()
+        } else {
+            self.shape[self.rank() - 2]
+        };
+        let mut shape = self.shape.clone();
+        *shape.last_mut().unwrap() = n;
+        let mut storage = vec![Dtype::from(0); shape.iter().product()];
+        if storage.is_empty() {
+            return Tensor::new(shape, storage);
+        }
+
+        with_matrices(&y, |matrices| {
+            for (bi, out) in storage.chunks_exact_mut(m * n).enumerate() {
+                let a = &self.storage[bi * m * k..(bi + 1) * m * k];
+                gemm_nt_blocked(a, matrices[bi], out, m, k, n);
+            }
+        });
+
+        Tensor::new(shape, storage)
     }
+}
 
-    #[inline]
-    pub fn matmul_(&self, y: Tensor<Dtype>) {}
+/// Calls `f` with the matrices of `y` (see [`TensorView::matrices`]), packing `y` into a
+/// dense copy first only if they are not already dense.
+fn with_matrices<Dtype: Copy, R>(y: &TensorView<'_, Dtype>, f: impl FnOnce(&[&[Dtype]]) -> R) -> R {
+    match y.matrices() {
+        Some(matrices) => f(&matrices),
+        None => f(&y.contiguous().view().matrices().unwrap()),
+    }
+}
+
+/// Accumulates the `(m, k) x (k, n)` product of the row-major matrices `a` and `b` into `out`.
+fn gemm_blocked<Dtype>(a: &[Dtype], b: &[Dtype], out: &mut [Dtype], m: usize, k: usize, n: usize)
+where
+    Dtype: Copy + Add<Output = Dtype> + Mul<Output = Dtype>,
+{
+    for i0 in (0..m).step_by(MATMUL_BLOCK) {
+        let i1 = (i0 + MATMUL_BLOCK).min(m);
+        for k0 in (0..k).step_by(MATMUL_BLOCK) {
+            let k1 = (k0 + MATMUL_BLOCK).min(k);
+            for j0 in (0..n).step_by(MATMUL_BLOCK) {
+                let j1 = (j0 + MATMUL_BLOCK).min(n);
+                for i in i0..i1 {
+                    let out = &mut out[i * n + j0..i * n + j1];
+                    for p in k0..k1 {
+                        let a_ip = a[i * k + p];
+                        let row = &b[p * n + j0..p * n + j1];
+                        for (o, &b_pj) in out.iter_mut().zip(row) {
+                            *o = *o + a_ip * b_pj;
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
 
-// Seen these implemented but don't exactly know what there used for
-// ==================================================================
-// #[derive(Debug, Clone, Copy)]
-// struct TensorSlice<'a, T: 'a>{
-//     ptr : *const T,
-//     shape: Shape<'a>,
-//     marker : PhantomData<&'a T>
-// }
-
-// #[derive(Debug, Clone, Copy)]
-// struct TensorSliceMut<'a, T: 'a>{
-//     ptr : *mut T,
-//     shape: _Shape<'a>,
-//     marker : PhantomData<&'a mut T>
-// }
-// ==================================================================
+/// Accumulates the `(m, k) x (n, k)^T` product of the row-major matrices `a` and `b` into `out`.
+///
+/// Like [`gemm_blocked`] it works in `MATMUL_BLOCK` sized tiles, including along `k`, so a
+/// tile of `a` and of `b` stays in cache while it is reused.
+fn gemm_nt_blocked<Dtype>(a: &[Dtype], b: &[Dtype], out: &mut [Dtype], m: usize, k: usize, n: usize)
+where
+    Dtype: Copy + From<u8> + Add<Output = Dtype> + Mul<Output = Dtype>,
+{
+    for i0 in (0..m).step_by(MATMUL_BLOCK) {
+        let i1 = (i0 + MATMUL_BLOCK).min(m);
+        for k0 in (0..k).step_by(MATMUL_BLOCK_K) {
+            let k1 = (k0 + MATMUL_BLOCK_K).min(k);
+            for j0 in (0..n).step_by(MATMUL_BLOCK) {
+                let j1 = (j0 + MATMUL_BLOCK).min(n);
+                for i in i0..i1 {
+                    let row = &a[i * k + k0..i * k + k1];
+                    for j in j0..j1 {
+                        let col = &b[j * k + k0..j * k + k1];
+                        out[i * n + j] = out[i * n + j] + dot(row, col);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Dot product of two equally long slices.
+///
+/// The products are summed into `DOT_LANES` independent accumulators rather than one, so
+/// consecutive additions do not wait on each other and the loop can be vectorised.
+fn dot<Dtype>(a: &[Dtype], b: &[Dtype]) -> Dtype
+where
+    Dtype: Copy + From<u8> + Add<Output = Dtype> + Mul<Output = Dtype>,
+{
+    let zero = Dtype::from(0);
+    let (a_chunks, b_chunks) = (a.chunks_exact(DOT_LANES), b.chunks_exact(DOT_LANES));
+    let tail = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .fold(zero, |acc, (&x, &w)| acc + x * w);
+    let mut lanes = [zero; DOT_LANES];
+    for (x, w) in a_chunks.zip(b_chunks) {
+        for l in 0..DOT_LANES {
+            lanes[l] = lanes[l] + x[l] * w[l];
+        }
+    }
+    lanes.into_iter().fold(tail, |acc, lane| acc + lane)
+}
+
+/// Edge length of the square tiles used by [`Tensor::matmul_`].
+const MATMUL_BLOCK: usize = 64;
+
+/// Length along `k` of the tiles used by [`Tensor::matmul_transposed_`].
+const MATMUL_BLOCK_K: usize = 256;
+
+/// Number of independent partial sums in [`dot`].
+const DOT_LANES: usize = 8;
 
 #[cfg(test)]
-mod test {
-    use super::{IntoTensor, Tensor, TensorError};
+pub(crate) mod test {
+    use super::{broadcast_shape, IntoTensor, Tensor, TensorError};
+
+    /// Builds a contiguous tensor of the given shape holding `0, 1, 2, ...`.
+    pub(crate) fn arange(shape: &[usize]) -> Tensor<i32> {
+        let n = shape.iter().product::<usize>() as i32;
+        Tensor::new(shape.to_vec(), (0..n).collect()).unwrap()
+    }
 
     #[test]
     fn vec_to_tensor_success() {
         let vec = vec![1, 2, 3, 4, 5, 6];
         let tensor: Tensor<i32> = vec.to_tensor(2, 3).unwrap();
-        assert_eq!(tensor.shape, (2, 3));
+        assert_eq!(tensor.shape, vec![2, 3]);
+        assert_eq!(tensor.strides, vec![3, 1]);
         assert_eq!(tensor.storage, vec![1, 2, 3, 4, 5, 6]);
     }
 
@@ -300,54 +680,54 @@ mod test {
         assert_eq!(
             result.unwrap_err(),
             TensorError::InvalidShape {
-                expected: (2, 3),
-                found: (5, 1)
+                expected: vec![2, 3],
+                found: vec![5]
             }
         );
     }
 
     #[test]
     fn tensor_rand_shape() {
-        let shape: (usize, usize) = (10, 10);
+        let shape = [10, 10];
         let tensor = Tensor::<f32>::rand_f32(shape);
         assert_eq!(tensor.shape, shape);
     }
 
     #[test]
     fn tensor_equality() {
-        let tensor1 = Tensor::new((2, 2), vec![1, 2, 3, 4]).unwrap();
-        let tensor2 = Tensor::new((2, 2), vec![1, 2, 3, 4]).unwrap();
-        let tensor3 = Tensor::new((2, 2), vec![4, 3, 2, 1]).unwrap();
+        let tensor1 = Tensor::new([2, 2], vec![1, 2, 3, 4]).unwrap();
+        let tensor2 = Tensor::new([2, 2], vec![1, 2, 3, 4]).unwrap();
+        let tensor3 = Tensor::new([2, 2], vec![4, 3, 2, 1]).unwrap();
         assert_eq!(tensor1, tensor2);
         assert_ne!(tensor1, tensor3);
     }
 
     #[test]
     fn test_retain_grad() {
-        let tensor = Tensor::new((2, 2), vec![1, 2, 3, 4]).unwrap();
+        let tensor = Tensor::new([2, 2], vec![1, 2, 3, 4]).unwrap();
         assert_eq!(cfg!(feature = "retain_gradients"), tensor.retain_grad())
     }
 
     #[test]
     fn test_addition() {
-        let tensor_a = Tensor::new((1, 2), vec![1, 2]).unwrap();
-        let tensor_b = Tensor::new((1, 2), vec![3, 4]).unwrap();
+        let tensor_a = Tensor::new([1, 2], vec![1, 2]).unwrap();
+        let tensor_b = Tensor::new([1, 2], vec![3, 4]).unwrap();
         let result = tensor_a.add_(tensor_b).unwrap();
         assert_eq!(result.storage, vec![4, 6]);
     }
 
     #[test]
     fn test_subtraction() {
-        let tensor_a = Tensor::new((1, 2), vec![5, 3]).unwrap();
-        let tensor_b = Tensor::new((1, 2), vec![2, 1]).unwrap();
+        let tensor_a = Tensor::new([1, 2], vec![5, 3]).unwrap();
+        let tensor_b = Tensor::new([1, 2], vec![2, 1]).unwrap();
         let result = tensor_a.sub_(tensor_b).unwrap();
         assert_eq!(result.storage, vec![3, 2]);
     }
 
     #[test]
     fn test_multiplication() {
-        let tensor_a = Tensor::new((1, 2), vec![2, 3]).unwrap();
-        let tensor_b = Tensor::new((1, 2), vec![3, 4]).unwrap();
+        let tensor_a = Tensor::new([1, 2], vec![2, 3]).unwrap();
+        let tensor_b = Tensor::new([1, 2], vec![3, 4]).unwrap();
         let result = tensor_a.mul_(tensor_b).unwrap();
         assert_eq!(result.storage, vec![6, 12]);
     }
@@ -355,11 +735,289 @@ mod test {
     #[test]
     #[should_panic(expected = "Tensors must have the same shape")]
     fn test_shape_mismatch() {
-        let tensor_a = Tensor::new((1, 2), vec![1, 2]).unwrap();
-        let tensor_b = Tensor::new((1, 3), vec![3, 4, 5]).unwrap();
+        let tensor_a = Tensor::new([1, 2], vec![1, 2]).unwrap();
+        let tensor_b = Tensor::new([1, 3], vec![3, 4, 5]).unwrap();
         match tensor_a.add_(tensor_b) {
             Ok(_) => panic!("Not possible"),
             Err(_) => panic!("Tensors must have the same shape"),
         }
     }
+
+    fn naive_matmul(a: &[i64], b: &[i64], m: usize, k: usize, n: usize) -> Vec<i64> {
+        let mut out = vec![0; m * n];
+        for i in 0..m {
+            for j in 0..n {
+                for p in 0..k {
+                    out[i * n + j] += a[i * k + p] * b[p * n + j];
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_matmul_small() {
+        let a = Tensor::new([2, 3], vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let b = Tensor::new([3, 2], vec![7, 8, 9, 10, 11, 12]).unwrap();
+        let result = a.matmul_(&b).unwrap();
+        assert_eq!(result.shape, vec![2, 2]);
+        assert_eq!(result.storage, vec![58, 64, 139, 154]);
+    }
+
+    #[test]
+    fn test_matmul_matches_naive_odd_shapes() {
+        // straddle the tile size so partial blocks are exercised on every axis
+        for &(m, k, n) in &[(1, 1, 1), (3, 5, 7), (65, 1, 3), (13, 129, 70), (67, 71, 1)] {
+            let a: Vec<i64> = (0..m * k).map(|x| (x as i64 * 7 + 3) % 11 - 5).collect();
+            let b: Vec<i64> = (0..k * n).map(|x| (x as i64 * 5 + 1) % 13 - 6).collect();
+            let expected = naive_matmul(&a, &b, m, k, n);
+
+            let ta = Tensor::new([m, k], a).unwrap();
+            let tb = Tensor::new([k, n], b).unwrap();
+            let result = ta.matmul_(&tb).unwrap();
+            assert_eq!(result.shape, vec![m, n]);
+            assert_eq!(result.storage, expected);
+        }
+    }
+
+    #[test]
+    fn test_matmul_zero_size() {
+        let a = Tensor::new([0, 3], Vec::<i64>::new()).unwrap();
+        let b = Tensor::new([3, 2], vec![1; 6]).unwrap();
+        let result = a.matmul_(&b).unwrap();
+        assert_eq!(result.shape, vec![0, 2]);
+        assert!(result.storage.is_empty());
+
+        let a = Tensor::new([2, 4, 3], vec![1; 24]).unwrap();
+        let b = Tensor::new([2, 3, 0], Vec::<i64>::new()).unwrap();
+        assert_eq!(a.matmul_(&b).unwrap().shape, vec![2, 4, 0]);
+
+        // an empty reduction gives zeros
+        let a = Tensor::new([2, 0], Vec::<i64>::new()).unwrap();
+        let b = Tensor::new([0, 3], Vec::<i64>::new()).unwrap();
+        assert_eq!(a.matmul_(&b).unwrap().storage, vec![0; 6]);
+        let a = Tensor::new([1, 2, 0], Vec::<i64>::new()).unwrap();
+        let w = Tensor::new([3, 0], Vec::<i64>::new()).unwrap();
+        let result = a.matmul_transposed_(&w).unwrap();
+        assert_eq!(result.shape, vec![1, 2, 3]);
+        assert_eq!(result.storage, vec![0; 6]);
+    }
+
+    #[test]
+    fn test_matmul_f32() {
+        let (m, k, n) = (9, 100, 33);
+        let a = Tensor::<f32>::rand_f32([m, k]);
+        let b = Tensor::<f32>::rand_f32([k, n]);
+        let result = a.matmul_(&b).unwrap();
+        for i in 0..m {
+            for j in 0..n {
+                let expected: f32 = (0..k)
+                    .map(|p| a.storage[i * k + p] * b.storage[p * n + j])
+                    .sum();
+                assert!((result.storage[i * n + j] - expected).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_matmul_transposed_matches_matmul() {
+        // k straddles both the k tile and the dot-product lanes
+        let (m, k, n) = (70, 301, 67);
+        let a = Tensor::new([m, k], (0..(m * k) as i64).map(|x| x % 11 - 5).collect()).unwrap();
+        let w = Tensor::new([n, k], (0..(n * k) as i64).map(|x| x % 7 - 3).collect()).unwrap();
+        let expected = a.matmul_(w.transpose(0, 1).unwrap()).unwrap();
+        assert_eq!(a.matmul_transposed_(&w).unwrap(), expected);
+
+        let a = Tensor::<f32>::rand_f32([m, k]);
+        let w = Tensor::<f32>::rand_f32([n, k]);
+        let expected = a.matmul_(w.transpose(0, 1).unwrap()).unwrap();
+        let result = a.matmul_transposed_(&w).unwrap();
+        assert_eq!(result.shape, vec![m, n]);
+        for (x, y) in result.storage.iter().zip(&expected.storage) {
+            assert!((x - y).abs() < 1e-3, "{} != {}", x, y);
+        }
+    }
+
+    #[test]
+    fn test_matmul_mismatch() {
+        let a = Tensor::new([2, 3], vec![0; 6]).unwrap();
+        let b = Tensor::new([2, 3], vec![0; 6]).unwrap();
+        assert_eq!(
+            a.matmul_(&b).unwrap_err(),
+            TensorError::ArithmeticMismatch {
+                operation: "matmul".to_string(),
+                shape1: vec![2, 3],
+                shape2: vec![2, 3],
+            }
+        );
+    }
+
+    #[test]
+    fn test_nd_shape_and_strides() {
+        let tensor = Tensor::new([2, 3, 4], (0..24).collect::<Vec<i32>>()).unwrap();
+        assert_eq!(tensor.rank(), 3);
+        assert_eq!(tensor.numel(), 24);
+        assert_eq!(tensor.strides, vec![12, 4, 1]);
+        assert!(tensor.is_contiguous());
+        assert_eq!(*tensor.get(&[1, 2, 3]).unwrap(), 23);
+        assert_eq!(tensor.dim(2), Ok(4));
+        assert_eq!(tensor.dim(3), Err(TensorError::OutOfBounds { index: 3 }));
+        assert_eq!(
+            tensor.get(&[0, 3, 0]),
+            Err(TensorError::OutOfBounds { index: 3 })
+        );
+    }
+
+    #[test]
+    fn test_nd_invalid_shape() {
+        let result = Tensor::new([2, 2, 2], vec![0; 7]);
+        assert_eq!(
+            result.unwrap_err(),
+            TensorError::InvalidShape {
+                expected: vec![2, 2, 2],
+                found: vec![7]
+            }
+        );
+    }
+
+    #[test]
+    fn test_batched_matmul() {
+        let (batch, m, k, n) = (3, 4, 5, 2);
+        let a: Vec<i64> = (0..(batch * m * k) as i64).collect();
+        let b: Vec<i64> = (0..(batch * k * n) as i64).map(|x| x % 7 - 3).collect();
+        let ta = Tensor::new([batch, m, k], a.clone()).unwrap();
+        let tb = Tensor::new([batch, k, n], b.clone()).unwrap();
+        let result = ta.matmul_(&tb).unwrap();
+        assert_eq!(result.shape, vec![batch, m, n]);
+        for i in 0..batch {
+            let expected = naive_matmul(
+                &a[i * m * k..(i + 1) * m * k],
+                &b[i * k * n..(i + 1) * k * n],
+                m,
+                k,
+                n,
+            );
+            assert_eq!(&result.storage[i * m * n..(i + 1) * m * n], &expected[..]);
+        }
+
+        // a 2-D right-hand side is shared across the batch
+        let shared = Tensor::new([k, n], b[..k * n].to_vec()).unwrap();
+        let result = ta.matmul_(&shared).unwrap();
+        for i in 0..batch {
+            let expected = naive_matmul(&a[i * m * k..(i + 1) * m * k], &b[..k * n], m, k, n);
+            assert_eq!(&result.storage[i * m * n..(i + 1) * m * n], &expected[..]);
+        }
+    }
+
+    #[test]
+    fn test_matmul_views() {
+        // a (2, 6, 3) buffer narrowed to its first 4 rows, like a partly filled KV cache
+        let buffer = Tensor::new([2, 6, 3], (0..36).map(|x| x % 5 - 2).collect()).unwrap();
+        let prefix = buffer.narrow(1, 0, 4).unwrap();
+        let dense = prefix.contiguous();
+        let matrices = prefix.matrices().unwrap();
+        assert_eq!(matrices[1].as_ptr(), buffer.storage[18..].as_ptr());
+
+        let a = Tensor::new([2, 5, 4], (0..40).map(|x| x % 7 - 3).collect()).unwrap();
+        assert_eq!(
+            a.matmul_(prefix.clone()).unwrap(),
+            a.matmul_(&dense).unwrap()
+        );
+        let q = Tensor::new([2, 5, 3], (0..30).map(|x| x % 3 - 1).collect()).unwrap();
+        let scores = q.matmul_transposed_(prefix.clone()).unwrap();
+        assert_eq!(scores.shape, vec![2, 5, 4]);
+        assert_eq!(scores, q.matmul_(dense.transpose(1, 2).unwrap()).unwrap());
+
+        // a transposed view has no dense matrices and is packed first
+        assert!(dense.transpose(1, 2).unwrap().matrices().is_none());
+        assert!(q
+            .matmul_transposed_(&Tensor::new([3, 4, 3], vec![0; 36]).unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_batched_matmul_mismatch() {
+        let a = Tensor::new([2, 2, 3], vec![0; 12]).unwrap();
+        let b = Tensor::new([3, 3, 2], vec![0; 18]).unwrap();
+        assert!(a.matmul_(&b).is_err());
+        let v = Tensor::new([3], vec![0; 3]).unwrap();
+        assert!(a.matmul_(&v).is_err());
+    }
+
+    #[test]
+    fn test_broadcast_shape() {
+        assert_eq!(broadcast_shape(&[4, 1, 3], &[2, 1]), Some(vec![4, 2, 3]));
+        assert_eq!(broadcast_shape(&[], &[2, 3]), Some(vec![2, 3]));
+        assert_eq!(broadcast_shape(&[2, 3], &[3, 2]), None);
+    }
+
+    #[test]
+    fn test_broadcast_bias() {
+        let x = Tensor::new([2, 3], vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let bias = Tensor::new([1, 3], vec![10, 20, 30]).unwrap();
+        let result = x.add_(bias).unwrap();
+        assert_eq!(result.shape, vec![2, 3]);
+        assert_eq!(result.storage, vec![11, 22, 33, 14, 25, 36]);
+    }
+
+    #[test]
+    fn test_broadcast_both_operands() {
+        let col = Tensor::new([3, 1], vec![1, 2, 3]).unwrap();
+        let row = Tensor::new([2], vec![10, 20]).unwrap();
+        let result = col.mul_(row).unwrap();
+        assert_eq!(result.shape, vec![3, 2]);
+        assert_eq!(result.storage, vec![10, 20, 20, 40, 30, 60]);
+
+        let result = Tensor::new([2], vec![10, 20])
+            .unwrap()
+            .sub_(Tensor::new([3, 1], vec![1, 2, 3]).unwrap())
+            .unwrap();
+        assert_eq!(result.storage, vec![9, 19, 8, 18, 7, 17]);
+    }
+
+    #[test]
+    fn test_scalar_ops() {
+        let x = Tensor::new([2, 2], vec![2.0, 4.0, 6.0, 8.0]).unwrap();
+        assert_eq!(x.add_scalar(1.0).storage, vec![3.0, 5.0, 7.0, 9.0]);
+        assert_eq!(x.sub_scalar(1.0).storage, vec![1.0, 3.0, 5.0, 7.0]);
+        assert_eq!(x.mul_scalar(0.5).storage, vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(x.div_scalar(2.0).storage, vec![1.0, 2.0, 3.0, 4.0]);
+
+        // a 0-d tensor broadcasts like a scalar
+        let s = Tensor::new(Vec::new(), vec![2.0]).unwrap();
+        assert_eq!(x.div_(s).unwrap().storage, vec![1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_broadcast_mismatch_names_operation() {
+        let a = Tensor::new([2, 3], vec![0; 6]).unwrap();
+        let b = Tensor::new([2], vec![1; 2]).unwrap();
+        assert_eq!(
+            a.div_(b).unwrap_err(),
+            TensorError::ArithmeticMismatch {
+                operation: "div".to_string(),
+                shape1: vec![2, 3],
+                shape2: vec![2],
+            }
+        );
+    }
+
+    #[test]
+    fn test_matmul_transposed() {
+        let (m, k, n) = (5, 70, 67);
+        let a: Vec<i64> = (0..m * k).map(|x| (x as i64 * 3 + 1) % 7 - 3).collect();
+        let b: Vec<i64> = (0..n * k).map(|x| (x as i64 * 5 + 2) % 9 - 4).collect();
+        // b is (n, k); build its (k, n) transpose for the reference
+        let bt: Vec<i64> = (0..k * n).map(|x| b[(x % n) * k + x / n]).collect();
+        let expected = naive_matmul(&a, &bt, m, k, n);
+
+        let ta = Tensor::new([1, m, k], a).unwrap();
+        let tb = Tensor::new([n, k], b).unwrap();
+        let result = ta.matmul_transposed_(&tb).unwrap();
+        assert_eq!(result.shape, vec![1, m, n]);
+        assert_eq!(result.storage, expected);
+
+        let bad = Tensor::new([n, k + 1], vec![0; n * (k + 1)]).unwrap();
+        assert!(ta.matmul_transposed_(&bad).is_err());
+    }
 }
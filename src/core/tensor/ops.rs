@@ -0,0 +1,203 @@
+// Operator overloads for `Tensor`, so model code can be written as `&x * &w + &b`.
+//
+// All binary operators broadcast like the `add_`/`sub_`/`mul_`/`div_` methods. Since the
+// `std::ops` traits cannot return a `Result`, a shape mismatch panics with the
+// `TensorError` message; use the methods when the shapes are not known to agree.
+
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use super::{broadcast_shape, Tensor};
+
+macro_rules! impl_binary_op {
+    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident, $op:tt, $name:literal) => {
+        impl<Dtype> $assign_trait<&Tensor<Dtype>> for Tensor<Dtype>
+        where
+            Dtype: Copy + $trait<Output = Dtype>,
+        {
+            fn $assign_method(&mut self, rhs: &Tensor<Dtype>) {
+                if let Err(e) = self.zip_assign(rhs, $name, |a, b| a $op b) {
+                    panic!("{}", e);
+                }
+            }
+        }
+
+        impl<Dtype> $assign_trait<Tensor<Dtype>> for Tensor<Dtype>
+        where
+            Dtype: Copy + $trait<Output = Dtype>,
+        {
+            fn $assign_method(&mut self, rhs: Tensor<Dtype>) {
+                self.$assign_method(&rhs);
+            }
+        }
+
+        impl<Dtype> $assign_trait<Dtype> for Tensor<Dtype>
+        where
+            Dtype: Copy + $trait<Output = Dtype>,
+        {
+            fn $assign_method(&mut self, rhs: Dtype) {
+                self.map_inplace(|a| a $op rhs);
+            }
+        }
+
+        impl<Dtype> $trait<&Tensor<Dtype>> for &Tensor<Dtype>
+        where
+            Dtype: Copy + $trait<Output = Dtype>,
+        {
+            type Output = Tensor<Dtype>;
+
+            fn $method(self, rhs: &Tensor<Dtype>) -> Tensor<Dtype> {
+                match self.zip_with(rhs, $name, |a, b| a $op b) {
+                    Ok(out) => out,
+                    Err(e) => panic!("{}", e),
+                }
+            }
+        }
+
+        impl<Dtype> $trait<&Tensor<Dtype>> for Tensor<Dtype>
+        where
+            Dtype: Copy + $trait<Output = Dtype>,
+        {
+            type Output = Tensor<Dtype>;
+
+            fn $method(mut self, rhs: &Tensor<Dtype>) -> Tensor<Dtype> {
+                // reuse our storage unless broadcasting has to grow it
+                if broadcast_shape(&self.shape, &rhs.shape).as_ref() != Some(&self.shape) {
+                    return &self $op rhs;
+                }
+                self.$assign_method(rhs);
+                self
+            }
+        }
+
+        impl<Dtype> $trait<Tensor<Dtype>> for Tensor<Dtype>
+        where
+            Dtype: Copy + $trait<Output = Dtype>,
+        {
+            type Output = Tensor<Dtype>;
+
+            fn $method(self, rhs: Tensor<Dtype>) -> Tensor<Dtype> {
+                self $op &rhs
+            }
+        }
+
+        impl<Dtype> $trait<Tensor<Dtype>> for &Tensor<Dtype>
+        where
+            Dtype: Copy + $trait<Output = Dtype>,
+        {
+            type Output = Tensor<Dtype>;
+
+            fn $method(self, rhs: Tensor<Dtype>) -> Tensor<Dtype> {
+                self $op &rhs
+            }
+        }
+
+        impl<Dtype> $trait<Dtype> for Tensor<Dtype>
+        where
+            Dtype: Copy + $trait<Output = Dtype>,
+        {
+            type Output = Tensor<Dtype>;
+
+            fn $method(mut self, rhs: Dtype) -> Tensor<Dtype> {
+                self.$assign_method(rhs);
+                self
+            }
+        }
+
+        impl<Dtype> $trait<Dtype> for &Tensor<Dtype>
+        where
+            Dtype: Copy + $trait<Output = Dtype>,
+        {
+            type Output = Tensor<Dtype>;
+
+            fn $method(self, rhs: Dtype) -> Tensor<Dtype> {
+                self.clone() $op rhs
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add, AddAssign, add_assign, +, "add");
+impl_binary_op!(Sub, sub, SubAssign, sub_assign, -, "sub");
+impl_binary_op!(Mul, mul, MulAssign, mul_assign, *, "mul");
+impl_binary_op!(Div, div, DivAssign, div_assign, /, "div");
+
+impl<Dtype> Neg for Tensor<Dtype>
+where
+    Dtype: Copy + Neg<Output = Dtype>,
+{
+    type Output = Tensor<Dtype>;
+
+    fn neg(mut self) -> Tensor<Dtype> {
+        self.map_inplace(|a| -a);
+        self
+    }
+}
+
+impl<Dtype> Neg for &Tensor<Dtype>
+where
+    Dtype: Copy + Neg<Output = Dtype>,
+{
+    type Output = Tensor<Dtype>;
+
+    fn neg(self) -> Tensor<Dtype> {
+        -self.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Tensor;
+
+    #[test]
+    fn test_reference_ops_do_not_consume() {
+        let x = Tensor::new([2, 2], vec![1, 2, 3, 4]).unwrap();
+        let w = Tensor::new([2, 2], vec![2, 2, 2, 2]).unwrap();
+        let b = Tensor::new([2], vec![1, -1]).unwrap();
+        let y = &x * &w + &b;
+        assert_eq!(y.storage, vec![3, 3, 7, 7]);
+        assert_eq!((&x - &w).storage, vec![-1, 0, 1, 2]);
+        assert_eq!((&x / &w).storage, vec![0, 1, 1, 2]);
+        // all inputs are still usable
+        assert_eq!(x.storage, vec![1, 2, 3, 4]);
+        assert_eq!(w.numel() + b.numel(), 6);
+    }
+
+    #[test]
+    fn test_broadcast_grows_left_operand() {
+        let row = Tensor::new([3], vec![1, 2, 3]).unwrap();
+        let m = Tensor::new([2, 3], vec![10, 20, 30, 40, 50, 60]).unwrap();
+        let y = &row - &m;
+        assert_eq!(y.shape, vec![2, 3]);
+        assert_eq!(y.storage, vec![-9, -18, -27, -39, -48, -57]);
+        let y = row - m;
+        assert_eq!(y.storage, vec![-9, -18, -27, -39, -48, -57]);
+    }
+
+    #[test]
+    fn test_scalar_and_neg() {
+        let x = Tensor::new([3], vec![1.0, 2.0, 3.0]).unwrap();
+        assert_eq!((&x * 2.0).storage, vec![2.0, 4.0, 6.0]);
+        assert_eq!((&x / 2.0).storage, vec![0.5, 1.0, 1.5]);
+        assert_eq!((-&x + 1.0).storage, vec![0.0, -1.0, -2.0]);
+        assert_eq!((x - 1.0).storage, vec![0.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_assign_reuses_allocation() {
+        let mut x = Tensor::new([2, 2], vec![1, 2, 3, 4]).unwrap();
+        let ptr = x.storage.as_ptr();
+        x += &Tensor::new([1, 2], vec![10, 20]).unwrap();
+        x *= 2;
+        x -= Tensor::new([2, 2], vec![1, 1, 1, 1]).unwrap();
+        x /= 3;
+        assert_eq!(x.storage, vec![7, 14, 8, 15]);
+        assert_eq!(x.storage.as_ptr(), ptr);
+    }
+
+    #[test]
+    #[should_panic(expected = "Arithmetic operation 'add'")]
+    fn test_assign_cannot_grow() {
+        let mut x = Tensor::new([2], vec![1, 2]).unwrap();
+        x += Tensor::new([2, 2], vec![1, 2, 3, 4]).unwrap();
+    }
+}
@@ -0,0 +1,307 @@
+use std::fs::File;
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+use std::ops::{Deref, DerefMut};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::core::tensor::Float;
+
+/// A read-only, private memory mapping of a whole file.
+#[cfg(unix)]
+pub struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// The mapping is never written through and lives until drop.
+#[cfg(unix)]
+unsafe impl Send for Mmap {}
+#[cfg(unix)]
+unsafe impl Sync for Mmap {}
+
+#[cfg(unix)]
+impl Mmap {
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            // mmap rejects empty mappings
+            return Ok(Mmap {
+                ptr: std::ptr::null_mut(),
+                len,
+            });
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Mmap { ptr, len })
+    }
+}
+
+#[cfg(unix)]
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+/// Memory mapping is only implemented on unix. Elsewhere no `Mmap` can exist, so no
+/// storage is ever [`Storage::Mapped`] and files are read into memory instead.
+#[cfg(not(unix))]
+pub enum Mmap {}
+
+#[cfg(not(unix))]
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match *self {}
+    }
+}
+
+/// The elements of a [`Tensor`](crate::core::tensor::Tensor): either an owned `Vec` or
+/// a borrowed range of a memory-mapped file, so checkpoint weights can be used in
+/// place instead of being copied onto the heap.
+///
+/// Both variants deref to `[Dtype]`. A mapped storage is read-only; the first mutable
+/// access copies it into an owned `Vec` (copy-on-write).
+pub enum Storage<Dtype> {
+    Owned(Vec<Dtype>),
+    Mapped(MappedSlice<Dtype>),
+}
+
+/// A checked range of a [`Mmap`], only built by [`Storage::mapped`]. Its fields are
+/// private so that `Deref` can trust the bounds, alignment and `Dtype` checks made there.
+pub struct MappedSlice<Dtype> {
+    map: Arc<Mmap>,
+    // in bytes
+    offset: usize,
+    // in elements
+    len: usize,
+    _dtype: PhantomData<Dtype>,
+}
+
+impl<Dtype> Storage<Dtype> {
+    /// `len` elements of `map` starting at byte `offset`, or `None` if the range is out
+    /// of the file or not aligned for `Dtype`.
+    pub fn mapped(map: Arc<Mmap>, offset: usize, len: usize) -> Option<Self>
+    where
+        Dtype: Float,
+    {
+        let end = len.checked_mul(size_of::<Dtype>())?.checked_add(offset)?;
+        let aligned = (map.as_ptr() as usize + offset).is_multiple_of(align_of::<Dtype>());
+        if end > map.len() || !aligned {
+            return None;
+        }
+        Some(Storage::Mapped(MappedSlice {
+            map,
+            offset,
+            len,
+            _dtype: PhantomData,
+        }))
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, Storage::Mapped(_))
+    }
+
+    /// The owned elements, copying them out of the mapping if needed.
+    pub fn to_mut(&mut self) -> &mut Vec<Dtype>
+    where
+        Dtype: Clone,
+    {
+        if let Storage::Mapped(_) = self {
+            *self = Storage::Owned(self.to_vec());
+        }
+        match self {
+            Storage::Owned(v) => v,
+            Storage::Mapped(_) => unreachable!(),
+        }
+    }
+
+    pub fn into_vec(self) -> Vec<Dtype>
+    where
+        Dtype: Clone,
+    {
+        match self {
+            Storage::Owned(v) => v,
+            mapped => mapped.to_vec(),
+        }
+    }
+}
+
+impl<Dtype> Deref for Storage<Dtype> {
+    type Target = [Dtype];
+
+    #[inline]
+    fn deref(&self) -> &[Dtype] {
+        match self {
+            Storage::Owned(v) => v,
+            // `Storage::mapped` checked bounds and alignment, and is only available for
+            // `Float` types, for which every bit pattern is a valid value
+            Storage::Mapped(MappedSlice {
+                map, offset, len, ..
+            }) => unsafe {
+                std::slice::from_raw_parts(map.as_ptr().add(*offset) as *const Dtype, *len)
+            },
+        }
+    }
+}
+
+impl<Dtype: Clone> DerefMut for Storage<Dtype> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [Dtype] {
+        self.to_mut()
+    }
+}
+
+impl<Dtype> From<Vec<Dtype>> for Storage<Dtype> {
+    fn from(v: Vec<Dtype>) -> Self {
+        Storage::Owned(v)
+    }
+}
+
+impl<'a, Dtype> IntoIterator for &'a Storage<Dtype> {
+    type Item = &'a Dtype;
+    type IntoIter = std::slice::Iter<'a, Dtype>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<Dtype: Clone> Clone for Storage<Dtype> {
+    fn clone(&self) -> Self {
+        match self {
+            Storage::Owned(v) => Storage::Owned(v.clone()),
+            Storage::Mapped(MappedSlice {
+                map, offset, len, ..
+            }) => Storage::Mapped(MappedSlice {
+                map: Arc::clone(map),
+                offset: *offset,
+                len: *len,
+                _dtype: PhantomData,
+            }),
+        }
+    }
+}
+
+impl<Dtype: std::fmt::Debug> std::fmt::Debug for Storage<Dtype> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<Dtype: PartialEq> PartialEq for Storage<Dtype> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<Dtype: Eq> Eq for Storage<Dtype> {}
+
+impl<Dtype: PartialEq> PartialEq<Vec<Dtype>> for Storage<Dtype> {
+    fn eq(&self, other: &Vec<Dtype>) -> bool {
+        **self == other[..]
+    }
+}
+
+impl<Dtype: PartialEq> PartialEq<Storage<Dtype>> for Vec<Dtype> {
+    fn eq(&self, other: &Storage<Dtype>) -> bool {
+        self[..] == **other
+    }
+}
+
+impl<Dtype: PartialEq, const N: usize> PartialEq<[Dtype; N]> for Storage<Dtype> {
+    fn eq(&self, other: &[Dtype; N]) -> bool {
+        **self == other[..]
+    }
+}
+
+impl<Dtype: Serialize> Serialize for Storage<Dtype> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, Dtype: Deserialize<'de>> Deserialize<'de> for Storage<Dtype> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Storage::Owned)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::fs;
+    use std::sync::Arc;
+
+    use super::{Mmap, Storage};
+
+    #[test]
+    fn test_mapped_copy_on_write() {
+        let path = std::env::temp_dir().join(format!("phi-2-mmap-{}", std::process::id()));
+        let bytes: Vec<u8> = [1.0f32, 2.0, 3.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        fs::write(&path, bytes).unwrap();
+        let map = Arc::new(Mmap::open(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let mut storage = Storage::<f32>::mapped(Arc::clone(&map), 4, 2).unwrap();
+        assert!(storage.is_mapped());
+        assert_eq!(storage, vec![2.0, 3.0]);
+        assert_eq!(storage.as_ptr() as usize, map.as_ptr() as usize + 4);
+
+        let shared = storage.clone();
+        storage[0] = 5.0;
+        assert!(!storage.is_mapped());
+        assert_eq!(storage, [5.0, 3.0]);
+        // the file and other views of it are untouched
+        assert_eq!(shared, vec![2.0, 3.0]);
+        assert!(shared.is_mapped());
+    }
+
+    #[test]
+    fn test_mapped_bounds_and_alignment() {
+        let path = std::env::temp_dir().join(format!("phi-2-mmap-align-{}", std::process::id()));
+        fs::write(&path, [0u8; 16]).unwrap();
+        let map = Arc::new(Mmap::open(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        assert!(Storage::<f64>::mapped(Arc::clone(&map), 8, 1).is_some());
+        assert!(Storage::<f64>::mapped(Arc::clone(&map), 8, 2).is_none());
+        assert!(Storage::<f32>::mapped(map, 2, 1).is_none());
+    }
+}
@@ -0,0 +1,487 @@
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use super::error::TensorError;
+use super::{contiguous_strides, Tensor};
+
+/// A borrowed, strided window onto the storage of a [`Tensor`].
+///
+/// Views are what `transpose`, `permute`, `reshape` and `narrow` return: they only
+/// rewrite `shape`, `strides` and `offset`, so no element is copied until a kernel
+/// needs dense memory and calls [`TensorView::contiguous`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorView<'a, Dtype> {
+    data: &'a [Dtype],
+    pub shape: Vec<usize>,
+    pub strides: Vec<usize>,
+    pub offset: usize,
+}
+
+impl<'a, Dtype> TensorView<'a, Dtype> {
+    pub(crate) fn new(data: &'a [Dtype], shape: Vec<usize>, strides: Vec<usize>) -> Self {
+        TensorView {
+            data,
+            shape,
+            strides,
+            offset: 0,
+        }
+    }
+
+    /// Number of dimensions.
+    #[inline]
+    pub fn rank(&self) -> usize {
+        self.shape.len()
+    }
+
+    /// Total number of elements in the view.
+    #[inline]
+    pub fn numel(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    /// Whether the view covers a dense row-major block of the underlying storage.
+    pub fn is_contiguous(&self) -> bool {
+        self.strides == contiguous_strides(&self.shape)
+    }
+
+    /// Element at a multi-dimensional index.
+    pub fn get(&self, index: &[usize]) -> Result<&'a Dtype, TensorError> {
+        if index.len() != self.rank() {
+            return Err(TensorError::InvalidShape {
+                expected: self.shape.clone(),
+                found: index.to_vec(),
+            });
+        }
+        let mut offset = self.offset;
+        for ((&i, &dim), &stride) in index.iter().zip(&self.shape).zip(&self.strides) {
+            if i >= dim {
+                return Err(TensorError::OutOfBounds { index: i });
+            }
+            offset += i * stride;
+        }
+        Ok(&self.data[offset])
+    }
+
+    /// Iterates over the elements in logical (row-major) order, following the strides.
+    pub fn iter(&self) -> StridedIter<'a, '_, Dtype> {
+        StridedIter {
+            view: self,
+            index: vec![0; self.rank()],
+            offset: self.offset,
+            remaining: self.numel(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Swaps dimensions `dim0` and `dim1`.
+    ///
+    /// # Example
+    /// ```
+    /// let t = Tensor::new([2, 3], vec![1, 2, 3, 4, 5, 6]).unwrap();
+    /// let tt = t.transpose(0, 1).unwrap();
+    /// assert_eq!(tt.shape, vec![3, 2]);
+    /// assert_eq!(tt.contiguous().storage, vec![1, 4, 2, 5, 3, 6]);
+    /// ```
+    pub fn transpose(mut self, dim0: usize, dim1: usize) -> Result<Self, TensorError> {
+        self.check_dim(dim0)?;
+        self.check_dim(dim1)?;
+        self.shape.swap(dim0, dim1);
+        self.strides.swap(dim0, dim1);
+        Ok(self)
+    }
+
+    /// Reorders the dimensions so that output dimension `i` is input dimension `dims[i]`.
+    pub fn permute(self, dims: &[usize]) -> Result<Self, TensorError> {
+        let mut seen = vec![false; self.rank()];
+        let valid = dims.len() == self.rank()
+            && dims
+                .iter()
+                .all(|&d| d < seen.len() && !std::mem::replace(&mut seen[d], true));
+        if !valid {
+            return Err(TensorError::InvalidShape {
+                expected: (0..self.rank()).collect(),
+                found: dims.to_vec(),
+            });
+        }
+
+        Ok(TensorView {
+            data: self.data,
+            shape: dims.iter().map(|&d| self.shape[d]).collect(),
+            strides: dims.iter().map(|&d| self.strides[d]).collect(),
+            offset: self.offset,
+        })
+    }
+
+    /// Reinterprets the view with a new shape holding the same number of elements.
+    ///
+    /// This never copies, so it requires a contiguous view; call
+    /// [`TensorView::contiguous`] first on transposed or permuted data.
+    pub fn reshape(self, shape: impl Into<Vec<usize>>) -> Result<Self, TensorError> {
+        let shape = shape.into();
+        if shape.iter().product::<usize>() != self.numel() {
+            return Err(TensorError::InvalidShape {
+                expected: shape,
+                found: self.shape,
+            });
+        }
+        if !self.is_contiguous() {
+            return Err(TensorError::NonContiguous {
+                shape: self.shape,
+                strides: self.strides,
+            });
+        }
+
+        Ok(TensorView {
+            data: self.data,
+            strides: contiguous_strides(&shape),
+            shape,
+            offset: self.offset,
+        })
+    }
+
+    /// Restricts dimension `dim` to the `len` entries starting at `start`.
+    pub fn narrow(mut self, dim: usize, start: usize, len: usize) -> Result<Self, TensorError> {
+        self.check_dim(dim)?;
+        match start.checked_add(len) {
+            Some(end) if end <= self.shape[dim] => {}
+            _ => {
+                return Err(TensorError::OutOfBounds {
+                    index: start.saturating_add(len),
+                })
+            }
+        }
+        self.offset += start * self.strides[dim];
+        self.shape[dim] = len;
+        Ok(self)
+    }
+
+    /// Range form of [`TensorView::narrow`].
+    pub fn slice(self, dim: usize, range: Range<usize>) -> Result<Self, TensorError> {
+        let len = range.end.saturating_sub(range.start);
+        self.narrow(dim, range.start, len)
+    }
+
+    /// Expands the view to `shape` by giving broadcast dimensions a stride of 0.
+    ///
+    /// Leading dimensions are added as needed and any size-1 dimension may be stretched;
+    /// every other dimension must already match.
+    pub fn broadcast_to(self, shape: impl Into<Vec<usize>>) -> Result<Self, TensorError> {
+        let shape = shape.into();
+        let mismatch = || TensorError::InvalidShape {
+            expected: shape.clone(),
+            found: self.shape.clone(),
+        };
+        if shape.len() < self.rank() {
+            return Err(mismatch());
+        }
+
+        let lead = shape.len() - self.rank();
+        let mut strides = vec![0; shape.len()];
+        for (i, (&dim, &stride)) in self.shape.iter().zip(&self.strides).enumerate() {
+            if dim == shape[lead + i] {
+                strides[lead + i] = stride;
+            } else if dim != 1 {
+                return Err(mismatch());
+            }
+        }
+
+        Ok(TensorView {
+            data: self.data,
+            shape,
+            strides,
+            offset: self.offset,
+        })
+    }
+
+    /// Copies the viewed elements into a new, densely packed tensor.
+    pub fn contiguous(&self) -> Tensor<Dtype>
+    where
+        Dtype: Copy,
+    {
+        let storage = if self.is_contiguous() {
+            self.data[self.offset..self.offset + self.numel()].to_vec()
+        } else {
+            self.iter().copied().collect()
+        };
+        Tensor::from_shape_vec(self.shape.clone(), storage).unwrap()
+    }
+
+    /// The matrices spanned by the last two dimensions, one per index of the leading
+    /// dimensions in row-major order, as slices of the underlying storage. `None` if the
+    /// view has fewer than two dimensions or its matrices are not dense row-major blocks,
+    /// as after a transpose of the last two dimensions.
+    ///
+    /// A view that narrows a leading or row dimension, like the cached prefix of a
+    /// [`KvCache`](crate::core::nn::KvCache), still has dense matrices.
+    pub(crate) fn matrices(&self) -> Option<Vec<&'a [Dtype]>> {
+        let rank = self.rank();
+        if rank < 2 {
+            return None;
+        }
+        let (rows, cols) = (self.shape[rank - 2], self.shape[rank - 1]);
+        if (cols > 1 && self.strides[rank - 1] != 1) || (rows > 1 && self.strides[rank - 2] != cols)
+        {
+            return None;
+        }
+
+        let batch = &self.shape[..rank - 2];
+        let count = batch.iter().product();
+        let mut index = vec![0; batch.len()];
+        let mut matrices = Vec::with_capacity(count);
+        for _ in 0..count {
+            let offset: usize = index
+                .iter()
+                .zip(&self.strides)
+                .map(|(i, stride)| i * stride)
+                .sum();
+            let start = self.offset + offset;
+            matrices.push(&self.data[start..start + rows * cols]);
+            for d in (0..batch.len()).rev() {
+                index[d] += 1;
+                if index[d] < batch[d] {
+                    break;
+                }
+                index[d] = 0;
+            }
+        }
+        Some(matrices)
+    }
+
+    fn check_dim(&self, dim: usize) -> Result<(), TensorError> {
+        if dim >= self.rank() {
+            return Err(TensorError::OutOfBounds { index: dim });
+        }
+        Ok(())
+    }
+}
+
+/// Row-major iterator over the elements of a [`TensorView`].
+pub struct StridedIter<'a, 'v, Dtype> {
+    view: &'v TensorView<'a, Dtype>,
+    index: Vec<usize>,
+    offset: usize,
+    remaining: usize,
+    _marker: PhantomData<&'a Dtype>,
+}
+
+impl<'a, Dtype> Iterator for StridedIter<'a, '_, Dtype> {
+    type Item = &'a Dtype;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let item = &self.view.data[self.offset];
+        self.remaining -= 1;
+
+        // odometer increment, carrying from the last dimension
+        for d in (0..self.index.len()).rev() {
+            self.index[d] += 1;
+            self.offset += self.view.strides[d];
+            if self.index[d] < self.view.shape[d] {
+                break;
+            }
+            self.offset -= self.index[d] * self.view.strides[d];
+            self.index[d] = 0;
+        }
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<Dtype> ExactSizeIterator for StridedIter<'_, '_, Dtype> {}
+
+impl<'a, Dtype> From<&'a Tensor<Dtype>> for TensorView<'a, Dtype> {
+    fn from(tensor: &'a Tensor<Dtype>) -> Self {
+        tensor.view()
+    }
+}
+
+impl<Dtype> Tensor<Dtype> {
+    /// Borrows the whole tensor as a [`TensorView`].
+    pub fn view(&self) -> TensorView<'_, Dtype> {
+        TensorView::new(&self.storage, self.shape.clone(), self.strides.clone())
+    }
+
+    /// See [`TensorView::transpose`].
+    pub fn transpose(
+        &self,
+        dim0: usize,
+        dim1: usize,
+    ) -> Result<TensorView<'_, Dtype>, TensorError> {
+        self.view().transpose(dim0, dim1)
+    }
+
+    /// See [`TensorView::permute`].
+    pub fn permute(&self, dims: &[usize]) -> Result<TensorView<'_, Dtype>, TensorError> {
+        self.view().permute(dims)
+    }
+
+    /// See [`TensorView::reshape`].
+    pub fn reshape(
+        &self,
+        shape: impl Into<Vec<usize>>,
+    ) -> Result<TensorView<'_, Dtype>, TensorError> {
+        self.view().reshape(shape)
+    }
+
+    /// See [`TensorView::narrow`].
+    pub fn narrow(
+        &self,
+        dim: usize,
+        start: usize,
+        len: usize,
+    ) -> Result<TensorView<'_, Dtype>, TensorError> {
+        self.view().narrow(dim, start, len)
+    }
+
+    /// See [`TensorView::slice`].
+    pub fn slice(
+        &self,
+        dim: usize,
+        range: Range<usize>,
+    ) -> Result<TensorView<'_, Dtype>, TensorError> {
+        self.view().slice(dim, range)
+    }
+
+    /// Reshapes an owned tensor in place without touching its storage.
+    pub fn into_shape(mut self, shape: impl Into<Vec<usize>>) -> Result<Self, TensorError> {
+        let shape = shape.into();
+        if shape.iter().product::<usize>() != self.numel() {
+            return Err(TensorError::InvalidShape {
+                expected: shape,
+                found: self.shape,
+            });
+        }
+        self.strides = contiguous_strides(&shape);
+        self.shape = shape;
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::error::TensorError;
+    use super::super::test::arange;
+    use super::super::Tensor;
+
+    #[test]
+    fn test_transpose() {
+        let t = arange(&[2, 3]);
+        let tt = t.transpose(0, 1).unwrap();
+        assert_eq!(tt.shape, vec![3, 2]);
+        assert_eq!(tt.strides, vec![1, 3]);
+        assert!(!tt.is_contiguous());
+        assert_eq!(*tt.get(&[2, 1]).unwrap(), 5);
+        assert_eq!(tt.contiguous().storage, vec![0, 3, 1, 4, 2, 5]);
+    }
+
+    #[test]
+    fn test_permute_matches_transpose_chain() {
+        let t = arange(&[2, 3, 4]);
+        let p = t.permute(&[2, 0, 1]).unwrap();
+        assert_eq!(p.shape, vec![4, 2, 3]);
+        for i in 0..4 {
+            for j in 0..2 {
+                for k in 0..3 {
+                    assert_eq!(p.get(&[i, j, k]), t.get(&[j, k, i]));
+                }
+            }
+        }
+        assert!(t.permute(&[0, 0, 1]).is_err());
+        assert!(t.permute(&[0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_reshape_requires_contiguous() {
+        let t = arange(&[2, 6]);
+        let r = t.reshape([3, 4]).unwrap();
+        assert_eq!(r.strides, vec![4, 1]);
+        assert_eq!(*r.get(&[2, 1]).unwrap(), 9);
+        assert_eq!(
+            t.reshape([5]).unwrap_err(),
+            TensorError::InvalidShape {
+                expected: vec![5],
+                found: vec![2, 6]
+            }
+        );
+        assert!(matches!(
+            t.transpose(0, 1).unwrap().reshape([12]),
+            Err(TensorError::NonContiguous { .. })
+        ));
+    }
+
+    #[test]
+    fn test_narrow_and_slice() {
+        let t = arange(&[3, 4]);
+        let n = t.narrow(1, 1, 2).unwrap();
+        assert_eq!(n.shape, vec![3, 2]);
+        assert_eq!(n.contiguous().storage, vec![1, 2, 5, 6, 9, 10]);
+
+        // a row slice stays contiguous and is copied as one block
+        let rows = t.slice(0, 1..3).unwrap();
+        assert!(rows.is_contiguous());
+        assert_eq!(rows.contiguous().storage, (4..12).collect::<Vec<_>>());
+
+        assert_eq!(
+            t.narrow(1, 3, 2).unwrap_err(),
+            TensorError::OutOfBounds { index: 5 }
+        );
+        assert_eq!(
+            t.narrow(2, 0, 1).unwrap_err(),
+            TensorError::OutOfBounds { index: 2 }
+        );
+        // `start + len` overflowing is out of bounds too, not a panic or a wrap-around
+        assert_eq!(
+            t.narrow(1, usize::MAX, 2).unwrap_err(),
+            TensorError::OutOfBounds { index: usize::MAX }
+        );
+        assert!(t.view().narrow(0, 1, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_head_split_round_trip() {
+        // (batch, seq, hidden) -> (batch, heads, seq, head_dim) and back
+        let (batch, seq, heads, head_dim) = (2, 3, 4, 5);
+        let x = arange(&[batch, seq, heads * head_dim]);
+        let split = x
+            .reshape([batch, seq, heads, head_dim])
+            .unwrap()
+            .transpose(1, 2)
+            .unwrap()
+            .contiguous();
+        assert_eq!(split.shape, vec![batch, heads, seq, head_dim]);
+        assert_eq!(split.get(&[1, 2, 0, 3]), x.get(&[1, 0, 2 * head_dim + 3]));
+
+        let merged = split.transpose(1, 2).unwrap().contiguous();
+        let merged = merged.into_shape([batch, seq, heads * head_dim]).unwrap();
+        assert_eq!(merged, x);
+    }
+
+    #[test]
+    fn test_iter_len() {
+        let t = arange(&[2, 3, 4]);
+        let v = t.narrow(2, 1, 2).unwrap();
+        assert_eq!(v.iter().len(), 12);
+        assert_eq!(
+            v.iter().copied().collect::<Vec<_>>(),
+            vec![1, 2, 5, 6, 9, 10, 13, 14, 17, 18, 21, 22]
+        );
+    }
+
+    #[test]
+    fn test_broadcast_to() {
+        let t = arange(&[3, 1]);
+        let b = t.view().broadcast_to([2, 3, 2]).unwrap();
+        assert_eq!(b.strides, vec![0, 1, 0]);
+        assert_eq!(
+            b.contiguous().storage,
+            vec![0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2]
+        );
+        assert!(t.view().broadcast_to([3, 2, 2]).is_err());
+        assert!(t.view().broadcast_to([3]).is_err());
+    }
+}
@@ -1,209 +0,0 @@
-use fancy_regex::Regex;
-use rustc_hash::FxHashMap as HashMap;
-
-type Rank = u32;
-
-
-pub fn byte_pair_encode(piece: &[u8], ranks: &HashMap<Vec<u8>, Rank>) -> Vec<Rank> {
-    assert!(piece.len() > 1);
-    _byte_pair_merge(&ranks, &piece)
-        .windows(2)
-        .map(|part| ranks[&piece[part[0].0..part[1].0]])
-        .collect()
-}
-
-pub fn byte_pair_split<'a>(piece: &'a [u8], ranks: &HashMap<Vec<u8>, Rank>) -> Vec<&'a [u8]> {
-    assert!(piece.len() > 1);
-    _byte_pair_merge(&ranks, &piece)
-        .windows(2)
-        .map(|part| &piece[part[0].0..part[1].0])
-        .collect()
-}
-struct CoreBPE {
-    encoder : HashMap<Vec<u8>, Rank>,
-    special_tokens_encoder: HashMap<String, Rank>,
-    decoder: HashMap<Rank, Vec<u8>>,
-    special_tokens_decoder: HashMap<Rank, Vec<u8>>,
-    regex : Regex,
-    sorted_token_bytes: Vec<Vec<u8>>,
-}
-
-fn _byte_pair_merge(ranks: &HashMap<Vec<u8>, Rank>, piece: &[u8]) -> Vec<(usize, Rank)> {
-    // This is a vector of (start, rank).
-    // The rank is of the pair starting at position start.
-    let mut parts = Vec::with_capacity(piece.len() + 1);
-
-    // Note that we hash bytes when indexing into `ranks`, not token pairs. As long as we train BPE
-    // the way we currently do, this is equivalent. An easy way to break this would be to decouple
-    // merge priority from token index or to prevent specific token merges.
-    let mut min_rank: (Rank, usize) = (Rank::MAX, usize::MAX);
-    for i in 0..piece.len() - 1 {
-        let rank = *ranks.get(&piece[i..i + 2]).unwrap_or(&Rank::MAX);
-        if rank < min_rank.0 {
-            min_rank = (rank, i);
-        }
-        parts.push((i, rank));
-    }
-    parts.push((piece.len() - 1, Rank::MAX));
-    parts.push((piece.len(), Rank::MAX));
-
-    let get_rank = {
-        #[inline(always)]
-        |parts: &Vec<(usize, Rank)>, i: usize| {
-            if (i + 3) < parts.len() {
-                // Similar to `piece[i..i + 2]` above. The +3 is because we haven't yet deleted
-                // parts[i + 1], see comment in the main loop.
-                *ranks
-                    .get(&piece[parts[i].0..parts[i + 3].0])
-                    .unwrap_or(&Rank::MAX)
-            } else {
-                Rank::MAX
-            }
-        }
-    };
-
-    // If you have n parts and m merges, this does O(mn) work.
-    // We could do something with a heap and do O(m log n) work.
-    // n is often very small so considerations like cache-locality outweigh the algorithmic
-    // complexity downsides of the `parts` vector.
-    while min_rank.0 != Rank::MAX {
-        let i = min_rank.1;
-        // Update parts[i] and parts[i - 1] before removing parts[i + 1], since
-        // `parts.remove(i + 1)` will thrash the cache.
-        if i > 0 {
-            parts[i - 1].1 = get_rank(&parts, i - 1);
-        }
-        parts[i].1 = get_rank(&parts, i);
-        parts.remove(i + 1);
-
-        min_rank = (Rank::MAX, usize::MAX);
-        for (i, &(_, rank)) in parts[..parts.len() - 1].iter().enumerate() {
-            if rank < min_rank.0 {
-                min_rank = (rank, i);
-            }
-        }
-    }
-    parts
-}
-
-
-impl CoreBPE {
-    fn new(
-        encoder: HashMap<Vec<u8>, Rank>,
-        special_tokens_encoder: HashMap<String, Rank>,
-        pattern: &str,
-    ) -> Self  {
-        let regex = Regex::new(pattern)
-            .map_err(|e| e.to_string()).unwrap();
-
-        let decoder: HashMap<Rank, Vec<u8>> =
-            encoder.iter().map(|(k, v)| (*v, k.clone())).collect();
-
-        assert!(
-            encoder.len() == decoder.len(),
-            "Encoder and decoder must be of equal length; maybe you had duplicate token indices in your encoder?"
-        );
-
-        let special_tokens_decoder: HashMap<Rank, Vec<u8>> = special_tokens_encoder
-            .iter()
-            .map(|(k, v)| (*v, k.as_bytes().to_vec()))
-            .collect();
-
-        // Clone because I don't know how to tell Rust I'm not going to change the map
-        let mut sorted_token_bytes: Vec<Vec<u8>> = encoder.keys().cloned().collect();
-        sorted_token_bytes.sort();
-
-        CoreBPE {
-            encoder,
-            special_tokens_encoder,
-            decoder,
-            special_tokens_decoder,
-            regex,
-            sorted_token_bytes,
-        }
-    }
-
-    fn encode(&self, text: &str) -> Vec<Rank> {
-        // This is the core of the encoding logic; the other functions in here
-        // just make things complicated :-)
-
-        let mut ret = vec![];
-        for mat in self.regex.find_iter(text) {
-            let piece = mat.unwrap().as_str().as_bytes();
-            match self.encoder.get(piece) {
-                Some(token) => ret.push(*token),
-                None => ret.extend(&byte_pair_encode(piece, &self.encoder)),
-            }
-        }
-        ret
-    }
-
-    fn decode(&self, tokens : &[Rank]) -> Vec<u8> {
-        let mut ret = Vec::with_capacity(tokens.len() * 2);
-        for token in tokens {
-            let token_bytes = self
-                .decoder
-                .get(token)
-                .unwrap_or_else(|| &self.special_tokens_decoder[token]);
-            ret.extend(token_bytes)
-        }
-        ret
-    }
-}
-
-
-#[cfg(test)]
-mod tests {
-    use rustc_hash::FxHashMap as HashMap;
-
-    use super::{byte_pair_split, Rank, CoreBPE};
-
-    fn setup_ranks() -> HashMap<Vec<u8>, Rank> {
-        HashMap::from_iter([
-            (b"ab".to_vec(), 0),
-            (b"cd".to_vec(), 1),
-            (b"ef".to_vec(), 2),
-        ])
-    }
-
-    #[test]
-    fn test_simple_characters() {
-        let ranks = setup_ranks();
-        let res = byte_pair_split(b"abcd", &ranks);
-        assert_eq!(res, vec![b"ab", b"cd"]);
-    }
-
-    #[test]
-    fn test_repeated_characters() {
-        let ranks = setup_ranks();
-        let res = byte_pair_split(b"abab", &ranks);
-        assert_eq!(res, vec![b"ab", b"ab"]);
-    }
-
-    #[test]
-    fn test_core_bpe_encoding() {
-        let ranks = setup_ranks();
-        let tokenizer = CoreBPE::new(ranks, HashMap::<String, Rank>::default(), r"\b(ab|cd|ef)\b");
-        let result = tokenizer.encode("ab ab cd ef");
-        assert_eq!(vec![0, 0, 1, 2], result);
-
-    }
-
-    #[test]
-    fn test_core_bpe_decoding() {
-        let ranks = setup_ranks();
-        let tokenizer = CoreBPE::new(ranks, HashMap::<String, Rank>::default(), r"\b(ab|cd|ef)\b");
-        let example: [u32; 4] = [0, 0, 1, 2];
-        let result = tokenizer.decode(&example);
-        let result_str = match String::from_utf8(result) {
-            Ok(r) => r,
-            Err(_) => {
-                "failed string".to_string()
-            }
-            
-        };
-        assert_eq!(result_str, "ababcdef");
-
-    }
-
-}
\ No newline at end of file
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use super::Rank;
+
+/// Errors raised while building or using a tokenizer.
+#[derive(Debug)]
+pub enum TokenizerError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Parse {
+        message: String,
+    },
+    InvalidVocab {
+        reason: String,
+    },
+    /// The input contains the text of a special token that is not allowed, at byte
+    /// `position`.
+    DisallowedSpecialToken {
+        token: String,
+        position: usize,
+    },
+    /// An id that is neither an ordinary nor a special token.
+    UnknownToken {
+        id: Rank,
+    },
+    /// The decoded bytes are not UTF-8; the first `valid_up_to` bytes are.
+    InvalidUtf8 {
+        valid_up_to: usize,
+    },
+}
+
+impl std::fmt::Display for TokenizerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenizerError::Io { path, source } => {
+                write!(f, "Failed to read '{}': {}", path.display(), source)
+            }
+            TokenizerError::Parse { message } => write!(f, "Invalid tokenizer file: {}", message),
+            TokenizerError::InvalidVocab { reason } => write!(f, "Invalid vocabulary: {}", reason),
+            TokenizerError::DisallowedSpecialToken { token, position } => write!(
+                f,
+                "Encountered disallowed special token '{}' at byte {}",
+                token, position
+            ),
+            TokenizerError::UnknownToken { id } => write!(f, "Unknown token id {}", id),
+            TokenizerError::InvalidUtf8 { valid_up_to } => {
+                write!(
+                    f,
+                    "Decoded text is not valid UTF-8 after byte {}",
+                    valid_up_to
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TokenizerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TokenizerError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,898 @@
+use std::fs;
+use std::path::Path;
+
+use fancy_regex::Regex;
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
+
+pub(crate) mod error;
+pub(crate) mod stream;
+
+use error::TokenizerError;
+pub use stream::DecodeStream;
+
+pub type Rank = u32;
+// token bytes -> rank
+type Encoder = HashMap<Vec<u8>, Rank>;
+
+/// Pre-tokenisation pattern of GPT-2, also used by CodeGen and phi-2: contractions,
+/// letter runs, digit runs and punctuation runs, each with an optional leading space,
+/// then whitespace.
+pub const GPT2_PATTERN: &str =
+    r"'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+(?!\S)|\s+";
+
+/// Stands in for unknown ids and invalid UTF-8 in lossy decoding.
+pub const REPLACEMENT: &str = "\u{FFFD}";
+
+pub fn byte_pair_encode(piece: &[u8], ranks: &HashMap<Vec<u8>, Rank>) -> Vec<Rank> {
+    assert!(piece.len() > 1);
+    _byte_pair_merge(ranks, piece)
+        .windows(2)
+        .map(|part| ranks[&piece[part[0].0..part[1].0]])
+        .collect()
+}
+
+pub fn byte_pair_split<'a>(piece: &'a [u8], ranks: &HashMap<Vec<u8>, Rank>) -> Vec<&'a [u8]> {
+    assert!(piece.len() > 1);
+    _byte_pair_merge(ranks, piece)
+        .windows(2)
+        .map(|part| &piece[part[0].0..part[1].0])
+        .collect()
+}
+
+/// GPT-2's reversible mapping of every byte onto a printable character, in rank order:
+/// the printable Latin-1 bytes map onto themselves, the others onto `U+0100..`. It is
+/// how `vocab.json`, `merges.txt` and `tokenizer.json` spell byte strings, e.g. a
+/// leading space as `Ġ` and a newline as `Ċ`.
+pub fn bytes_to_unicode() -> Vec<(u8, char)> {
+    let mut bytes: Vec<u8> = (b'!'..=b'~').chain(0xA1..=0xAC).chain(0xAE..=0xFF).collect();
+    let mut chars: Vec<char> = bytes.iter().map(|&b| b as char).collect();
+    let mut n = 0;
+    for b in 0..=255u8 {
+        if !bytes.contains(&b) {
+            bytes.push(b);
+            chars.push(char::from_u32(256 + n).unwrap());
+            n += 1;
+        }
+    }
+    bytes.into_iter().zip(chars).collect()
+}
+
+fn read_file(path: &Path) -> Result<String, TokenizerError> {
+    fs::read_to_string(path).map_err(|source| TokenizerError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn parse_error(e: impl std::fmt::Display) -> TokenizerError {
+    TokenizerError::Parse {
+        message: e.to_string(),
+    }
+}
+
+/// [`bytes_to_unicode`] as lookup tables both ways, built once per tokenizer.
+struct ByteLevel {
+    encoder: [char; 256],
+    decoder: HashMap<char, u8>,
+}
+
+impl ByteLevel {
+    fn new() -> Self {
+        let mut encoder = ['\0'; 256];
+        let mut decoder = HashMap::default();
+        for (byte, c) in bytes_to_unicode() {
+            encoder[byte as usize] = c;
+            decoder.insert(c, byte);
+        }
+        ByteLevel { encoder, decoder }
+    }
+
+    /// The byte-level spelling of `bytes`.
+    fn encode(&self, bytes: &[u8]) -> String {
+        bytes.iter().map(|&b| self.encoder[b as usize]).collect()
+    }
+
+    /// Turns the byte-level spelling of a token back into its bytes.
+    fn decode(&self, token: &str) -> Result<Vec<u8>, TokenizerError> {
+        token
+            .chars()
+            .map(|c| {
+                self.decoder.get(&c).copied().ok_or_else(|| TokenizerError::InvalidVocab {
+                    reason: format!("'{}' in token '{}' is not a byte-level character", c, token),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Builds the BPE ranks of a byte-level vocabulary: the 256 single bytes in
+/// [`bytes_to_unicode`] order, then one rank per merge in file order. Entries of `vocab`
+/// that are not produced by any merge, like `<|endoftext|>`, are returned as special
+/// tokens.
+///
+/// tiktoken uses a token's rank as its id, so every merged token must have the id of
+/// its rank in `vocab`, as is the case for GPT-2, CodeGen and phi-2.
+fn byte_level_ranks(
+    vocab: &HashMap<String, Rank>,
+    merges: &[(String, String)],
+    byte_level: &ByteLevel,
+) -> Result<(Encoder, HashMap<String, Rank>), TokenizerError> {
+    let mut ranks: Encoder = HashMap::default();
+    for (rank, (byte, _)) in bytes_to_unicode().into_iter().enumerate() {
+        ranks.insert(vec![byte], rank as Rank);
+    }
+    for (first, second) in merges {
+        let mut merged = byte_level.decode(first)?;
+        merged.extend(byte_level.decode(second)?);
+        let rank = ranks.len() as Rank;
+        ranks.entry(merged).or_insert(rank);
+    }
+
+    let mut special_tokens = HashMap::default();
+    for (token, &id) in vocab {
+        let rank = byte_level.decode(token).ok().and_then(|bytes| ranks.get(&bytes));
+        match rank {
+            Some(&rank) if rank == id => {}
+            Some(&rank) => {
+                return Err(TokenizerError::InvalidVocab {
+                    reason: format!("token '{}' has id {} but merge rank {}", token, id, rank),
+                })
+            }
+            None => {
+                special_tokens.insert(token.clone(), id);
+            }
+        }
+    }
+    Ok((ranks, special_tokens))
+}
+
+fn parse_merges(merges: &str) -> Result<Vec<(String, String)>, TokenizerError> {
+    merges
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with("#version"))
+        .map(|line| {
+            line.split_once(' ')
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .ok_or_else(|| parse_error(format!("merge '{}' is not a pair", line)))
+        })
+        .collect()
+}
+
+struct CoreBPE {
+    encoder : HashMap<Vec<u8>, Rank>,
+    special_tokens_encoder: HashMap<String, Rank>,
+    // added tokens that are not special: matched in any text, like Hugging Face does
+    added_tokens_encoder: HashMap<String, Rank>,
+    decoder: HashMap<Rank, Vec<u8>>,
+    // special and added tokens
+    special_tokens_decoder: HashMap<Rank, Vec<u8>>,
+    regex : Regex,
+    // matches any special or added token, `None` when there are none
+    special_regex: Option<Regex>,
+    sorted_token_bytes: Vec<Vec<u8>>,
+    byte_level: ByteLevel,
+}
+
+fn _byte_pair_merge(ranks: &HashMap<Vec<u8>, Rank>, piece: &[u8]) -> Vec<(usize, Rank)> {
+    // This is a vector of (start, rank).
+    // The rank is of the pair starting at position start.
+    let mut parts = Vec::with_capacity(piece.len() + 1);
+
+    // Note that we hash bytes when indexing into `ranks`, not token pairs. As long as we train BPE
+    // the way we currently do, this is equivalent. An easy way to break this would be to decouple
+    // merge priority from token index or to prevent specific token merges.
+    let mut min_rank: (Rank, usize) = (Rank::MAX, usize::MAX);
+    for i in 0..piece.len() - 1 {
+        let rank = *ranks.get(&piece[i..i + 2]).unwrap_or(&Rank::MAX);
+        if rank < min_rank.0 {
+            min_rank = (rank, i);
+        }
+        parts.push((i, rank));
+    }
+    parts.push((piece.len() - 1, Rank::MAX));
+    parts.push((piece.len(), Rank::MAX));
+
+    let get_rank = {
+        #[inline(always)]
+        |parts: &Vec<(usize, Rank)>, i: usize| {
+            if (i + 3) < parts.len() {
+                // Similar to `piece[i..i + 2]` above. The +3 is because we haven't yet deleted
+                // parts[i + 1], see comment in the main loop.
+                *ranks
+                    .get(&piece[parts[i].0..parts[i + 3].0])
+                    .unwrap_or(&Rank::MAX)
+            } else {
+                Rank::MAX
+            }
+        }
+    };
+
+    // If you have n parts and m merges, this does O(mn) work.
+    // We could do something with a heap and do O(m log n) work.
+    // n is often very small so considerations like cache-locality outweigh the algorithmic
+    // complexity downsides of the `parts` vector.
+    while min_rank.0 != Rank::MAX {
+        let i = min_rank.1;
+        // Update parts[i] and parts[i - 1] before removing parts[i + 1], since
+        // `parts.remove(i + 1)` will thrash the cache.
+        if i > 0 {
+            parts[i - 1].1 = get_rank(&parts, i - 1);
+        }
+        parts[i].1 = get_rank(&parts, i);
+        parts.remove(i + 1);
+
+        min_rank = (Rank::MAX, usize::MAX);
+        for (i, &(_, rank)) in parts[..parts.len() - 1].iter().enumerate() {
+            if rank < min_rank.0 {
+                min_rank = (rank, i);
+            }
+        }
+    }
+    parts
+}
+
+
+impl CoreBPE {
+    fn new(
+        encoder: HashMap<Vec<u8>, Rank>,
+        special_tokens_encoder: HashMap<String, Rank>,
+        pattern: &str,
+    ) -> Self  {
+        CoreBPE::from_parts(
+            encoder,
+            special_tokens_encoder,
+            HashMap::default(),
+            pattern,
+            ByteLevel::new(),
+        )
+    }
+
+    fn from_parts(
+        encoder: HashMap<Vec<u8>, Rank>,
+        special_tokens_encoder: HashMap<String, Rank>,
+        added_tokens_encoder: HashMap<String, Rank>,
+        pattern: &str,
+        byte_level: ByteLevel,
+    ) -> Self {
+        let regex = Regex::new(pattern)
+            .map_err(|e| e.to_string()).unwrap();
+
+        let decoder: HashMap<Rank, Vec<u8>> =
+            encoder.iter().map(|(k, v)| (*v, k.clone())).collect();
+
+        assert!(
+            encoder.len() == decoder.len(),
+            "Encoder and decoder must be of equal length; maybe you had duplicate token indices in your encoder?"
+        );
+
+        let special_tokens_decoder: HashMap<Rank, Vec<u8>> = special_tokens_encoder
+            .iter()
+            .chain(&added_tokens_encoder)
+            .map(|(k, v)| (*v, k.as_bytes().to_vec()))
+            .collect();
+
+        // longest first, so a special token that is a prefix of another does not win
+        let mut specials: Vec<&str> = special_tokens_encoder
+            .keys()
+            .chain(added_tokens_encoder.keys())
+            .map(|s| s.as_str())
+            .collect();
+        specials.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        let special_regex = if specials.is_empty() {
+            None
+        } else {
+            let pattern: Vec<String> = specials.iter().map(|s| fancy_regex::escape(s).into_owned()).collect();
+            Some(Regex::new(&pattern.join("|")).unwrap())
+        };
+
+        // Clone because I don't know how to tell Rust I'm not going to change the map
+        let mut sorted_token_bytes: Vec<Vec<u8>> = encoder.keys().cloned().collect();
+        sorted_token_bytes.sort();
+
+        CoreBPE {
+            encoder,
+            special_tokens_encoder,
+            added_tokens_encoder,
+            decoder,
+            special_tokens_decoder,
+            regex,
+            special_regex,
+            sorted_token_bytes,
+            byte_level,
+        }
+    }
+
+    /// Builds a GPT-2 style byte-level tokenizer from the contents of a `vocab.json` and
+    /// a `merges.txt`.
+    fn from_vocab_and_merges(vocab: &str, merges: &str) -> Result<Self, TokenizerError> {
+        let vocab: HashMap<String, Rank> = serde_json::from_str(vocab).map_err(parse_error)?;
+        let byte_level = ByteLevel::new();
+        let (encoder, special_tokens_encoder) =
+            byte_level_ranks(&vocab, &parse_merges(merges)?, &byte_level)?;
+        Ok(CoreBPE::from_parts(
+            encoder,
+            special_tokens_encoder,
+            HashMap::default(),
+            GPT2_PATTERN,
+            byte_level,
+        ))
+    }
+
+    /// Reads `vocab.json` and `merges.txt`, see [`CoreBPE::from_vocab_and_merges`].
+    fn from_vocab_and_merges_files(
+        vocab: impl AsRef<Path>,
+        merges: impl AsRef<Path>,
+    ) -> Result<Self, TokenizerError> {
+        CoreBPE::from_vocab_and_merges(&read_file(vocab.as_ref())?, &read_file(merges.as_ref())?)
+    }
+
+    /// Builds a byte-level tokenizer from the contents of a Hugging Face `tokenizer.json`.
+    /// Entries of `added_tokens` marked `special`, such as `<|endoftext|>`, become special
+    /// tokens; the others, such as phi-2's whitespace runs, are matched in any text.
+    fn from_tokenizer_json(json: &str) -> Result<Self, TokenizerError> {
+        let json: serde_json::Value = serde_json::from_str(json).map_err(parse_error)?;
+        let model = &json["model"];
+        if model["type"] != "BPE" {
+            return Err(parse_error(format!("unsupported model type {}", model["type"])));
+        }
+        let vocab: HashMap<String, Rank> =
+            serde_json::from_value(model["vocab"].clone()).map_err(parse_error)?;
+        // merges are "a b" strings, or ["a", "b"] pairs since tokenizers 0.20
+        let merges = model["merges"]
+            .as_array()
+            .ok_or_else(|| parse_error("model.merges is missing"))?
+            .iter()
+            .map(|merge| match merge {
+                serde_json::Value::String(line) => match parse_merges(line)?.as_slice() {
+                    [pair] => Ok(pair.clone()),
+                    _ => Err(parse_error(format!("merge {} is not a pair", merge))),
+                },
+                serde_json::Value::Array(pair) => match (pair.first(), pair.get(1)) {
+                    (Some(serde_json::Value::String(a)), Some(serde_json::Value::String(b))) => {
+                        Ok((a.clone(), b.clone()))
+                    }
+                    _ => Err(parse_error(format!("merge {} is not a pair", merge))),
+                },
+                _ => Err(parse_error(format!("merge {} is not a pair", merge))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let byte_level = ByteLevel::new();
+        let (encoder, mut special_tokens_encoder) = byte_level_ranks(&vocab, &merges, &byte_level)?;
+        let mut added_tokens_encoder = HashMap::default();
+        for added in json["added_tokens"].as_array().into_iter().flatten() {
+            let (Some(content), Some(id)) = (added["content"].as_str(), added["id"].as_u64()) else {
+                return Err(parse_error(format!("added token {} needs a content and an id", added)));
+            };
+            // `special` defaults to false, as in Hugging Face's `AddedToken`
+            if added["special"].as_bool().unwrap_or(false) {
+                special_tokens_encoder.insert(content.to_string(), id as Rank);
+            } else {
+                special_tokens_encoder.remove(content);
+                added_tokens_encoder.insert(content.to_string(), id as Rank);
+            }
+        }
+        Ok(CoreBPE::from_parts(
+            encoder,
+            special_tokens_encoder,
+            added_tokens_encoder,
+            GPT2_PATTERN,
+            byte_level,
+        ))
+    }
+
+    /// Reads a `tokenizer.json`, see [`CoreBPE::from_tokenizer_json`].
+    fn from_tokenizer_file(path: impl AsRef<Path>) -> Result<Self, TokenizerError> {
+        CoreBPE::from_tokenizer_json(&read_file(path.as_ref())?)
+    }
+
+    fn encode(&self, text: &str) -> Vec<Rank> {
+        // This is the core of the encoding logic; the other functions in here
+        // just make things complicated :-)
+
+        let mut ret = vec![];
+        for mat in self.regex.find_iter(text) {
+            let piece = mat.unwrap().as_str().as_bytes();
+            match self.encoder.get(piece) {
+                Some(token) => ret.push(*token),
+                None => ret.extend(&byte_pair_encode(piece, &self.encoder)),
+            }
+        }
+        ret
+    }
+
+    /// The first special token in `text` at or after byte `start` that `accept`s.
+    fn find_special<'t>(
+        &self,
+        text: &'t str,
+        mut start: usize,
+        accept: impl Fn(&str) -> bool,
+    ) -> Option<fancy_regex::Match<'t>> {
+        let special_regex = self.special_regex.as_ref()?;
+        while let Some(mat) = special_regex.find_from_pos(text, start).unwrap() {
+            if accept(mat.as_str()) {
+                return Some(mat);
+            }
+            start = mat.start() + text[mat.start()..].chars().next().unwrap().len_utf8();
+        }
+        None
+    }
+
+    /// Encodes `text`, turning added tokens and the special tokens in `allowed_special`
+    /// into their ids and treating any other special token text as ordinary text.
+    fn encode_native(&self, text: &str, allowed_special: &HashSet<&str>) -> Vec<Rank> {
+        if allowed_special.is_empty() && self.added_tokens_encoder.is_empty() {
+            return self.encode(text);
+        }
+        let accept = |s: &str| self.added_tokens_encoder.contains_key(s) || allowed_special.contains(s);
+        let mut ret = vec![];
+        let mut start = 0;
+        loop {
+            let next_special = self.find_special(text, start, accept);
+            let end = next_special.map_or(text.len(), |mat| mat.start());
+            ret.extend(self.encode(&text[start..end]));
+
+            match next_special {
+                Some(mat) => {
+                    let token = mat.as_str();
+                    ret.push(
+                        *self
+                            .added_tokens_encoder
+                            .get(token)
+                            .unwrap_or_else(|| &self.special_tokens_encoder[token]),
+                    );
+                    start = mat.end();
+                }
+                None => break,
+            }
+        }
+        ret
+    }
+
+    /// The bytes of an ordinary or special token.
+    fn token_bytes(&self, token: Rank) -> Option<&[u8]> {
+        self.decoder
+            .get(&token)
+            .or_else(|| self.special_tokens_decoder.get(&token))
+            .map(|bytes| bytes.as_slice())
+    }
+
+    fn decode(&self, tokens : &[Rank]) -> Result<Vec<u8>, TokenizerError> {
+        let mut ret = Vec::with_capacity(tokens.len() * 2);
+        for &token in tokens {
+            let token_bytes = self
+                .token_bytes(token)
+                .ok_or(TokenizerError::UnknownToken { id: token })?;
+            ret.extend(token_bytes)
+        }
+        Ok(ret)
+    }
+
+    /// Like [`CoreBPE::decode`], with [`REPLACEMENT`] in place of unknown ids.
+    fn decode_lossy(&self, tokens: &[Rank]) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(tokens.len() * 2);
+        for &token in tokens {
+            ret.extend(self.token_bytes(token).unwrap_or(REPLACEMENT.as_bytes()))
+        }
+        ret
+    }
+}
+
+/// A set of special tokens for [`Tokenizer::encode_with_policy`], like tiktoken's
+/// `allowed_special` and `disallowed_special` arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecialTokens<'a> {
+    /// Every special token of the tokenizer.
+    All,
+    Only(HashSet<&'a str>),
+}
+
+impl<'a> SpecialTokens<'a> {
+    pub fn none() -> Self {
+        SpecialTokens::Only(HashSet::default())
+    }
+
+    fn resolve(&self, bpe: &'a CoreBPE) -> HashSet<&'a str> {
+        match self {
+            SpecialTokens::All => bpe.special_tokens_encoder.keys().map(|s| s.as_str()).collect(),
+            SpecialTokens::Only(tokens) => tokens.clone(),
+        }
+    }
+}
+
+/// A byte-level BPE tokenizer, as used by GPT-2 and phi-2.
+///
+/// Ids below [`Tokenizer::vocab_size`] are either merged byte strings or special tokens
+/// such as `<|endoftext|>`. Tokens are named by their byte-level spelling, the one used
+/// in `vocab.json` and `tokenizer.json`, e.g. `Ġthe` for " the".
+pub struct Tokenizer {
+    bpe: CoreBPE,
+}
+
+impl Tokenizer {
+    /// Reads a Hugging Face `tokenizer.json`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, TokenizerError> {
+        CoreBPE::from_tokenizer_file(path).map(|bpe| Tokenizer { bpe })
+    }
+
+    /// Reads a GPT-2 style `vocab.json` and `merges.txt`.
+    pub fn from_vocab_and_merges_files(
+        vocab: impl AsRef<Path>,
+        merges: impl AsRef<Path>,
+    ) -> Result<Self, TokenizerError> {
+        CoreBPE::from_vocab_and_merges_files(vocab, merges).map(|bpe| Tokenizer { bpe })
+    }
+
+    /// Reads the tokenizer of a model directory: its `tokenizer.json` if there is one,
+    /// else its `vocab.json` and `merges.txt`.
+    pub fn from_pretrained(dir: impl AsRef<Path>) -> Result<Self, TokenizerError> {
+        let dir = dir.as_ref();
+        let json = dir.join("tokenizer.json");
+        if json.is_file() {
+            Tokenizer::from_file(json)
+        } else {
+            Tokenizer::from_vocab_and_merges_files(dir.join("vocab.json"), dir.join("merges.txt"))
+        }
+    }
+
+    /// Encodes `text` as ordinary text: special token text is split like any other, while
+    /// added tokens that are not special are still matched.
+    pub fn encode(&self, text: &str) -> Vec<Rank> {
+        self.bpe.encode_native(text, &HashSet::default())
+    }
+
+    /// Encodes `text`, mapping the special tokens listed in `allowed` onto their ids.
+    pub fn encode_with_special_tokens(&self, text: &str, allowed: &HashSet<&str>) -> Vec<Rank> {
+        self.bpe.encode_native(text, allowed)
+    }
+
+    /// Encodes `text` under a special token policy, the way tiktoken's `encode` does:
+    /// special tokens in `allowed` become their ids, and an error is returned if `text`
+    /// contains one in `disallowed`. Any other special token text is encoded as ordinary
+    /// text.
+    ///
+    /// `disallowed` of [`SpecialTokens::All`] means every special token not in `allowed`,
+    /// so `encode_with_policy(text, &SpecialTokens::none(), &SpecialTokens::All)` rejects
+    /// user text that tries to inject `<|endoftext|>`.
+    pub fn encode_with_policy(
+        &self,
+        text: &str,
+        allowed: &SpecialTokens,
+        disallowed: &SpecialTokens,
+    ) -> Result<Vec<Rank>, TokenizerError> {
+        let allowed = allowed.resolve(&self.bpe);
+        let disallowed: HashSet<&str> = match disallowed {
+            SpecialTokens::All => disallowed
+                .resolve(&self.bpe)
+                .difference(&allowed)
+                .copied()
+                .collect(),
+            SpecialTokens::Only(tokens) => tokens.clone(),
+        };
+        if !disallowed.is_empty() {
+            if let Some(mat) = self.bpe.find_special(text, 0, |s| disallowed.contains(s)) {
+                return Err(TokenizerError::DisallowedSpecialToken {
+                    token: mat.as_str().to_string(),
+                    position: mat.start(),
+                });
+            }
+        }
+        Ok(self.bpe.encode_native(text, &allowed))
+    }
+
+    /// The bytes of `tokens`, or [`TokenizerError::UnknownToken`] for the first id that
+    /// is not in the vocabulary. A single token may end inside a UTF-8 character.
+    pub fn decode(&self, tokens: &[Rank]) -> Result<Vec<u8>, TokenizerError> {
+        self.bpe.decode(tokens)
+    }
+
+    /// Decodes `tokens` without failing: unknown ids and invalid UTF-8 become
+    /// [`REPLACEMENT`].
+    pub fn decode_lossy(&self, tokens: &[Rank]) -> String {
+        String::from_utf8_lossy(&self.bpe.decode_lossy(tokens)).into_owned()
+    }
+
+    pub fn decode_to_string(&self, tokens: &[Rank]) -> Result<String, TokenizerError> {
+        String::from_utf8(self.decode(tokens)?).map_err(|e| TokenizerError::InvalidUtf8 {
+            valid_up_to: e.utf8_error().valid_up_to(),
+        })
+    }
+
+    /// A [`DecodeStream`] for decoding generated tokens one at a time.
+    pub fn decode_stream(&self) -> DecodeStream<'_> {
+        DecodeStream::new(self)
+    }
+
+    /// The id of a special or added token, or of a token in its byte-level spelling.
+    pub fn token_to_id(&self, token: &str) -> Option<Rank> {
+        let special = self.bpe.special_tokens_encoder.get(token);
+        if let Some(&id) = special.or_else(|| self.bpe.added_tokens_encoder.get(token)) {
+            return Some(id);
+        }
+        let bytes = self.bpe.byte_level.decode(token).ok()?;
+        self.bpe.encoder.get(&bytes).copied()
+    }
+
+    /// The inverse of [`Tokenizer::token_to_id`].
+    pub fn id_to_token(&self, id: Rank) -> Option<String> {
+        if let Some(bytes) = self.bpe.decoder.get(&id) {
+            return Some(self.bpe.byte_level.encode(bytes));
+        }
+        let bytes = self.bpe.special_tokens_decoder.get(&id)?;
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    /// The number of distinct ids, special and added tokens included. An added token
+    /// that reuses an id of the vocabulary is counted once.
+    pub fn vocab_size(&self) -> usize {
+        let decoder = &self.bpe.decoder;
+        let added = self.bpe.special_tokens_decoder.keys().filter(|id| !decoder.contains_key(id));
+        decoder.len() + added.count()
+    }
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
+    use serde_json::json;
+
+    use super::{
+        byte_pair_split, bytes_to_unicode, CoreBPE, Rank, SpecialTokens, Tokenizer, TokenizerError,
+    };
+
+    fn setup_ranks() -> HashMap<Vec<u8>, Rank> {
+        HashMap::from_iter([
+            (b"ab".to_vec(), 0),
+            (b"cd".to_vec(), 1),
+            (b"ef".to_vec(), 2),
+        ])
+    }
+
+    #[test]
+    fn test_simple_characters() {
+        let ranks = setup_ranks();
+        let res = byte_pair_split(b"abcd", &ranks);
+        assert_eq!(res, vec![b"ab", b"cd"]);
+    }
+
+    #[test]
+    fn test_repeated_characters() {
+        let ranks = setup_ranks();
+        let res = byte_pair_split(b"abab", &ranks);
+        assert_eq!(res, vec![b"ab", b"ab"]);
+    }
+
+    #[test]
+    fn test_core_bpe_encoding() {
+        let ranks = setup_ranks();
+        let tokenizer = CoreBPE::new(ranks, HashMap::<String, Rank>::default(), r"\b(ab|cd|ef)\b");
+        let result = tokenizer.encode("ab ab cd ef");
+        assert_eq!(vec![0, 0, 1, 2], result);
+
+    }
+
+    #[test]
+    fn test_core_bpe_decoding() {
+        let ranks = setup_ranks();
+        let tokenizer = CoreBPE::new(ranks, HashMap::<String, Rank>::default(), r"\b(ab|cd|ef)\b");
+        let example: [u32; 4] = [0, 0, 1, 2];
+        let result = tokenizer.decode(&example).unwrap();
+        let result_str = match String::from_utf8(result) {
+            Ok(r) => r,
+            Err(_) => {
+                "failed string".to_string()
+            }
+            
+        };
+        assert_eq!(result_str, "ababcdef");
+
+    }
+
+    /// A byte-level vocabulary with four merges on top of the 256 bytes.
+    pub(crate) fn tiny_vocab() -> (String, String) {
+        let mut vocab: Vec<(String, Rank)> = bytes_to_unicode()
+            .into_iter()
+            .enumerate()
+            .map(|(rank, (_, c))| (c.to_string(), rank as Rank))
+            .collect();
+        let merges = ["Ġ t", "h e", "Ġt he", "l l"];
+        for (i, merge) in merges.iter().enumerate() {
+            vocab.push((merge.replace(' ', ""), 256 + i as Rank));
+        }
+        vocab.push(("<|endoftext|>".to_string(), 260));
+        let vocab: serde_json::Map<String, serde_json::Value> =
+            vocab.into_iter().map(|(k, v)| (k, v.into())).collect();
+        (
+            serde_json::to_string(&vocab).unwrap(),
+            format!("#version: 0.2\n{}\n", merges.join("\n")),
+        )
+    }
+
+    /// [`tiny_vocab`] loaded from `vocab.json` and `merges.txt` contents.
+    pub(crate) fn tiny_tokenizer() -> Tokenizer {
+        let (vocab, merges) = tiny_vocab();
+        Tokenizer {
+            bpe: CoreBPE::from_vocab_and_merges(&vocab, &merges).unwrap(),
+        }
+    }
+
+    /// [`tiny_vocab`] loaded from a `tokenizer.json` with the given `added_tokens` array.
+    fn tiny_tokenizer_json(added_tokens: serde_json::Value) -> Tokenizer {
+        let (vocab, merges) = tiny_vocab();
+        let merges: Vec<&str> = merges.lines().skip(1).collect();
+        let json = format!(
+            r#"{{"added_tokens": {}, "model": {{"type": "BPE", "vocab": {}, "merges": {}}}}}"#,
+            added_tokens,
+            vocab,
+            serde_json::to_string(&merges).unwrap()
+        );
+        Tokenizer {
+            bpe: CoreBPE::from_tokenizer_json(&json).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_bytes_to_unicode() {
+        let table: HashMap<u8, char> = bytes_to_unicode().into_iter().collect();
+        assert_eq!(table.len(), 256);
+        assert_eq!((table[&b'a'], table[&b' '], table[&b'\n']), ('a', 'Ġ', 'Ċ'));
+        // a space is rank 220, as in GPT-2
+        assert_eq!(bytes_to_unicode()[220], (b' ', 'Ġ'));
+    }
+
+    #[test]
+    fn test_vocab_and_merges() {
+        let (vocab, merges) = tiny_vocab();
+        let tokenizer = CoreBPE::from_vocab_and_merges(&vocab, &merges).unwrap();
+        assert_eq!(tokenizer.special_tokens_encoder["<|endoftext|>"], 260);
+        // " the" merges fully; " hello" splits into Ġ, he, ll, o
+        assert_eq!(tokenizer.encode(" the hello"), vec![258, 220, 257, 259, 78]);
+        assert_eq!(tokenizer.decode(&[258, 220, 257, 259, 78, 260]).unwrap(), b" the hello<|endoftext|>");
+        // the GPT-2 pattern keeps contractions and trailing whitespace apart
+        let text = "it's  the\n";
+        assert_eq!(tokenizer.decode(&tokenizer.encode(text)).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn test_tokenizer_json() {
+        let tokenizer = tiny_tokenizer_json(json!([
+            {"id": 260, "content": "<|endoftext|>", "special": true},
+            {"id": 261, "content": "        ", "special": false},
+            {"id": 258, "content": " the", "special": false},
+        ]));
+        assert_eq!(tokenizer.bpe.encode(" the"), vec![258]);
+        assert_eq!(tokenizer.bpe.special_tokens_encoder.len(), 1);
+        assert_eq!(tokenizer.bpe.added_tokens_encoder.len(), 2);
+        assert_eq!(tokenizer.decode(&[261, 260]).unwrap(), b"        <|endoftext|>");
+        // " the" is also in the vocabulary, so only 261 adds an id
+        assert_eq!(tokenizer.vocab_size(), 262);
+
+        // added tokens that are not special match in ordinary text, special ones do not
+        let tokens = tokenizer.encode("a        b<|endoftext|>");
+        assert_eq!(tokens[..3], [64, 261, 65]);
+        assert!(!tokens.contains(&260));
+        assert_eq!(tokenizer.token_to_id("        "), Some(261));
+    }
+
+    #[test]
+    fn test_invalid_vocab() {
+        let (vocab, _) = tiny_vocab();
+        // "he" would have rank 256 with only one merge, but the vocabulary says 257
+        let result = CoreBPE::from_vocab_and_merges(&vocab, "h e\n");
+        assert!(matches!(result, Err(TokenizerError::InvalidVocab { .. })));
+        assert!(matches!(
+            CoreBPE::from_vocab_and_merges("[1, 2]", ""),
+            Err(TokenizerError::Parse { .. })
+        ));
+        // a merge string must hold exactly one pair
+        for merge in [json!(""), json!("#version: 0.2"), json!("a b\nc d"), json!(1)] {
+            let json = format!(
+                r#"{{"model": {{"type": "BPE", "vocab": {}, "merges": [{}]}}}}"#,
+                vocab, merge
+            );
+            assert!(matches!(
+                CoreBPE::from_tokenizer_json(&json),
+                Err(TokenizerError::Parse { .. })
+            ));
+        }
+        assert!(matches!(
+            CoreBPE::from_vocab_and_merges_files("missing/vocab.json", "missing/merges.txt"),
+            Err(TokenizerError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn test_tokenizer_api() {
+        let tokenizer = tiny_tokenizer();
+        assert_eq!(tokenizer.vocab_size(), 261);
+        assert_eq!(tokenizer.token_to_id("Ġthe"), Some(258));
+        assert_eq!(tokenizer.token_to_id("<|endoftext|>"), Some(260));
+        assert_eq!(tokenizer.token_to_id("Ġhello"), None);
+        assert_eq!(tokenizer.id_to_token(258).as_deref(), Some("Ġthe"));
+        assert_eq!(tokenizer.id_to_token(260).as_deref(), Some("<|endoftext|>"));
+        assert_eq!(tokenizer.id_to_token(261), None);
+
+        let text = " the<|endoftext|> the";
+        let ordinary = tokenizer.encode(text);
+        assert!(!ordinary.contains(&260));
+        assert_eq!(tokenizer.encode_with_special_tokens(text, &HashSet::default()), ordinary);
+        let allowed = HashSet::from_iter(["<|endoftext|>"]);
+        assert_eq!(tokenizer.encode_with_special_tokens(text, &allowed), vec![258, 260, 258]);
+        assert_eq!(tokenizer.decode_to_string(&[258, 260, 258]).unwrap(), text);
+
+        // "é" is two bytes, 0xC3 0xA9
+        let e_acute = tokenizer.encode("é");
+        assert_eq!(e_acute.len(), 2);
+        assert!(matches!(
+            tokenizer.decode_to_string(&e_acute[..1]),
+            Err(TokenizerError::InvalidUtf8 { valid_up_to: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_from_pretrained() {
+        let (vocab, merges) = tiny_vocab();
+        let dir = std::env::temp_dir().join(format!("phi-2-tokenizer-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("vocab.json"), vocab).unwrap();
+        std::fs::write(dir.join("merges.txt"), merges).unwrap();
+        let tokenizer = Tokenizer::from_pretrained(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(tokenizer.unwrap().encode(" the"), vec![258]);
+        assert!(matches!(
+            Tokenizer::from_pretrained("missing"),
+            Err(TokenizerError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn test_special_token_policy() {
+        let tokenizer = tiny_tokenizer_json(json!([
+            {"id": 260, "content": "<|endoftext|>", "special": true},
+            {"id": 261, "content": "<|end|>", "special": true},
+        ]));
+        let text = " the<|endoftext|><|end|>";
+        let all = SpecialTokens::All;
+        let none = SpecialTokens::none();
+        let only = |token| SpecialTokens::Only(HashSet::from_iter([token]));
+
+        assert_eq!(tokenizer.encode_with_policy(text, &all, &all).unwrap(), vec![258, 260, 261]);
+        // text of special tokens that are neither allowed nor disallowed is ordinary text
+        let ordinary = tokenizer.encode_with_policy(text, &none, &none).unwrap();
+        assert_eq!(ordinary, tokenizer.encode(text));
+        let endoftext = tokenizer.encode_with_policy(text, &only("<|endoftext|>"), &none).unwrap();
+        assert_eq!(endoftext[..2], [258, 260]);
+        assert!(!endoftext.contains(&261));
+
+        // by default every special token that is not allowed is disallowed
+        let error = tokenizer.encode_with_policy(text, &only("<|endoftext|>"), &all);
+        assert!(matches!(
+            error,
+            Err(TokenizerError::DisallowedSpecialToken { ref token, position: 17 }) if token == "<|end|>"
+        ));
+        let error = tokenizer.encode_with_policy(text, &none, &only("<|endoftext|>"));
+        assert!(matches!(
+            error,
+            Err(TokenizerError::DisallowedSpecialToken { position: 4, .. })
+        ));
+        assert!(tokenizer.encode_with_policy(" the", &none, &all).is_ok());
+    }
+
+    #[test]
+    fn test_decode_unknown_token() {
+        let tokenizer = tiny_tokenizer();
+        assert!(matches!(
+            tokenizer.decode(&[258, 261, 9999]),
+            Err(TokenizerError::UnknownToken { id: 261 })
+        ));
+        assert!(matches!(
+            tokenizer.decode_to_string(&[9999]),
+            Err(TokenizerError::UnknownToken { id: 9999 })
+        ));
+        assert_eq!(tokenizer.decode_lossy(&[258, 261, 260]), " the\u{FFFD}<|endoftext|>");
+        // half of "é" is invalid UTF-8
+        let e_acute = tokenizer.encode("é");
+        assert_eq!(tokenizer.decode_lossy(&[e_acute[0], 258]), "\u{FFFD} the");
+    }
+}
@@ -0,0 +1,123 @@
+use super::error::TokenizerError;
+use super::{Rank, Tokenizer, REPLACEMENT};
+
+/// Decodes tokens one at a time, as they are generated, into UTF-8 text.
+///
+/// A byte-level token can end in the middle of a multi-byte character, e.g. "é" is
+/// usually two tokens. The bytes of an incomplete character are held back until the
+/// tokens that complete it arrive, so every fragment returned by [`DecodeStream::step`]
+/// is valid text and the fragments concatenate to the decoded string. Bytes that can
+/// never form a character are replaced by [`REPLACEMENT`].
+pub struct DecodeStream<'a> {
+    tokenizer: &'a Tokenizer,
+    // bytes of an incomplete character
+    pending: Vec<u8>,
+}
+
+impl<'a> DecodeStream<'a> {
+    pub fn new(tokenizer: &'a Tokenizer) -> Self {
+        DecodeStream {
+            tokenizer,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Adds `token` and returns the text it completes, if any. An unknown id is an error
+    /// and leaves the stream unchanged.
+    pub fn step(&mut self, token: Rank) -> Result<Option<String>, TokenizerError> {
+        let bytes = self
+            .tokenizer
+            .bpe
+            .token_bytes(token)
+            .ok_or(TokenizerError::UnknownToken { id: token })?;
+        self.pending.extend_from_slice(bytes);
+
+        let mut text = String::new();
+        loop {
+            match std::str::from_utf8(&self.pending) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    self.pending.clear();
+                    break;
+                }
+                Err(e) => {
+                    let valid_up_to = e.valid_up_to();
+                    text.push_str(std::str::from_utf8(&self.pending[..valid_up_to]).unwrap());
+                    match e.error_len() {
+                        // the end of the bytes is the start of a character, wait for more
+                        None => {
+                            self.pending.drain(..valid_up_to);
+                            break;
+                        }
+                        Some(len) => {
+                            text.push_str(REPLACEMENT);
+                            self.pending.drain(..valid_up_to + len);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(if text.is_empty() { None } else { Some(text) })
+    }
+
+    /// Whether bytes of an incomplete character are held back.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Ends the stream and returns the held back bytes, which can no longer be completed,
+    /// as [`REPLACEMENT`]s.
+    pub fn finish(self) -> Option<String> {
+        if self.pending.is_empty() {
+            None
+        } else {
+            Some(String::from_utf8_lossy(&self.pending).into_owned())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::tests::tiny_tokenizer;
+    use super::super::{TokenizerError, REPLACEMENT};
+
+    #[test]
+    fn test_stream_splits_characters() {
+        let tokenizer = tiny_tokenizer();
+        // without merges every byte of "é" and "🦀" is its own token
+        let text = " the café 🦀!";
+        let tokens = tokenizer.encode(text);
+        let mut stream = tokenizer.decode_stream();
+        let mut fragments = vec![];
+        for &token in &tokens {
+            if let Some(fragment) = stream.step(token).unwrap() {
+                fragments.push(fragment);
+            }
+        }
+        assert!(!stream.is_pending());
+        assert_eq!(stream.finish(), None);
+        assert_eq!(fragments.concat(), text);
+        assert!(fragments.contains(&"é".to_string()));
+        assert!(fragments.contains(&"🦀".to_string()));
+    }
+
+    #[test]
+    fn test_stream_invalid_bytes() {
+        let tokenizer = tiny_tokenizer();
+        let crab = tokenizer.encode("🦀");
+        let mut stream = tokenizer.decode_stream();
+        assert_eq!(stream.step(crab[0]).unwrap(), None);
+        assert!(matches!(
+            stream.step(9999),
+            Err(TokenizerError::UnknownToken { id: 9999 })
+        ));
+        assert_eq!(stream.step(crab[1]).unwrap(), None);
+        // a space cannot continue the character
+        assert_eq!(
+            stream.step(tokenizer.encode(" ")[0]).unwrap().unwrap(),
+            format!("{} ", REPLACEMENT)
+        );
+        assert_eq!(stream.step(crab[0]).unwrap(), None);
+        assert_eq!(stream.finish().unwrap(), REPLACEMENT);
+    }
+}
@@ -1,11 +1,8 @@
 #![allow(dead_code)]
 
-use crate::nn::{Embedding, Linear};
+use crate::core::nn::{Embedding, Linear};
 
-mod config;
-mod tokeizer;
-mod nn;
-mod tensor;
+mod core;
 
 
 
@@ -43,9 +40,9 @@ fn main() ->  Result<(), ()>{
     let mut x: Embedding<f32> = Embedding::<f32>::new(3, 1);
     let mut _l: Linear<f32> = Linear::new(10, 10, false);
 
-    x.weight.push(1.0);
-    x.weight.push(2.0);
-    x.weight.push(3.0);
+    x.weight.to_mut().push(1.0);
+    x.weight.to_mut().push(2.0);
+    x.weight.to_mut().push(3.0);
     // println!("{:?}", &x[1]);
     println!("{}", x.to_toml());
     println!("Hello world welcome to Phi-2...");